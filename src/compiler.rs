@@ -1,15 +1,64 @@
 use crate::{lexer::Token, vm::Instruction};
 
-#[derive(Default)]
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CompileOptions {
+    /// Lets a line break separate statements, just like ';'.
+    pub newline_separators: bool,
+    /// Compiles numbers written without a decimal point as exact 64-bit integers, which stay
+    /// integers through arithmetic with each other and only become floats when mixed with one.
+    pub integer_literals: bool,
+    /// Folds arithmetic on literals and propagates variables bound to a constant, see `optimizer`.
+    pub optimize: bool,
+    /// Reads juxtaposition as multiplication (examples: '2x', '(x)(y)'). When off, an explicit '*'
+    /// is required and '2x' is a parse error.
+    pub implicit_multiplication: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            newline_separators: false,
+            integer_literals: false,
+            optimize: false,
+            implicit_multiplication: true,
+        }
+    }
+}
+
 pub struct Compiler<'a> {
     position: usize,
     program: &'a [Token],
+    depth: usize,
+    max_depth: usize,
+    preserve_groups: bool,
+    implicit_multiplication: bool,
+    optimize: bool,
+    error: Option<String>,
+    // the error from the speculative parse that got furthest before being reverted, with the
+    // position it reached
+    backtracked: Option<(usize, String)>,
+    warnings: Vec<String>,
+}
+
+impl Default for Compiler<'_> {
+    fn default() -> Self {
+        Self::new(&[])
+    }
 }
 
 #[derive(Debug)]
-pub(crate) enum RecursiveExpression {
+pub enum RecursiveExpression {
+    Sequence(Vec<RecursiveExpression>),
     Block(Vec<RecursiveExpression>),
     Literal(f64),
+    LiteralInt(i64),
+    Group(Box<RecursiveExpression>),
+    Tuple(Vec<RecursiveExpression>),
+    LiteralStr(String),
+    Array(Vec<RecursiveExpression>),
+    Range(Box<RecursiveExpression>, Box<RecursiveExpression>, bool),
     Local(String),
     FuncDeclaration(Vec<String>, Box<RecursiveExpression>),
     If(Box<RecursiveExpression>, Box<RecursiveExpression>),
@@ -19,13 +68,56 @@ pub(crate) enum RecursiveExpression {
         Box<RecursiveExpression>,
     ),
     AssignOp(String, Box<RecursiveExpression>),
+    ConstAssign(String, Box<RecursiveExpression>),
+    AssignExpr(String, Box<RecursiveExpression>),
+    IndexAssign(String, Box<RecursiveExpression>, Box<RecursiveExpression>),
+    DestructureAssign(Vec<String>, Box<RecursiveExpression>),
     BinaryOp(Box<RecursiveExpression>, BinaryOp, Box<RecursiveExpression>),
+    Not(Box<RecursiveExpression>),
+    Factorial(Box<RecursiveExpression>),
+    Index(Box<RecursiveExpression>, Box<RecursiveExpression>),
     Func0(Func0Op),
     Func1(Func1Op, Box<RecursiveExpression>),
+    Func2(Func2Op, Box<RecursiveExpression>, Box<RecursiveExpression>),
+    Func3(
+        Func3Op,
+        Box<RecursiveExpression>,
+        Box<RecursiveExpression>,
+        Box<RecursiveExpression>,
+    ),
+    FuncN(FuncNOp, Vec<RecursiveExpression>),
     FuncLocal(String, Vec<RecursiveExpression>),
+    Call(Box<RecursiveExpression>, Vec<RecursiveExpression>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltinFunction {
+    pub name: String,
+    /// The numbers of arguments accepted (example: 0 or 2 for 'rand_normal'), or empty where any
+    /// number is (example: 'min').
+    pub arities: Vec<usize>,
 }
+
+/// The names of the functions and constants available without being defined, see `builtins`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Builtins {
+    pub functions: Vec<BuiltinFunction>,
+    pub constants: Vec<String>,
+}
+
+impl RecursiveExpression {
+    /// True for an 'if' that has no final 'else', which runs as a statement and has no value.
+    pub fn yields_nothing(&self) -> bool {
+        match self {
+            Self::If(..) => true,
+            Self::IfElse(_, _, else_block) => else_block.yields_nothing(),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
-pub(crate) enum BinaryOp {
+pub enum BinaryOp {
     Add,
     Sub,
     Div,
@@ -58,7 +150,7 @@ impl BinaryOp {
             _ => None,
         }
     }
-    fn to_instruction(&self) -> Instruction {
+    pub(crate) fn to_instruction(self) -> Instruction {
         match self {
             Self::Add => Instruction::Add,
             Self::Sub => Instruction::Sub,
@@ -76,7 +168,8 @@ impl BinaryOp {
     }
     pub fn precedence(&self) -> usize {
         match self {
-            Self::Pow | Self::Mod => 3,
+            Self::Pow => 4,
+            Self::Mod => 3,
             Self::Mul | Self::Div => 2,
             Self::Add | Self::Sub => 1,
             Self::EQ | Self::NEQ | Self::LT | Self::LTE | Self::GT | Self::GTE => 0,
@@ -85,17 +178,45 @@ impl BinaryOp {
 }
 
 #[derive(Debug)]
-pub(crate) enum Func0Op {
+pub enum Func0Op {
     Rand,
+    RandNormal,
 }
 #[derive(Debug)]
-pub(crate) enum Func1Op {
+pub enum Func1Op {
     Sin,
     Cos,
     Sqrt,
     Log,
     Round,
     Floor,
+    Log2,
+    Csc,
+    Sec,
+    Cot,
+    Erfc,
+    Erf,
+    Gamma,
+    Print,
+    Clamp01,
+}
+#[derive(Debug)]
+pub enum Func2Op {
+    Map,
+    Simulate,
+    Beta,
+    Pow,
+    RandNormal,
+}
+#[derive(Debug)]
+pub enum Func3Op {
+    Reduce,
+    Lerp,
+}
+#[derive(Debug)]
+pub enum FuncNOp {
+    Min,
+    Max,
 }
 
 impl<'a> Compiler<'a> {
@@ -103,9 +224,45 @@ impl<'a> Compiler<'a> {
         Self {
             position: Default::default(),
             program: program,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            preserve_groups: false,
+            implicit_multiplication: true,
+            optimize: false,
+            error: None,
+            backtracked: None,
+            warnings: vec![],
         }
     }
 
+    /// Limits how deeply expressions may nest before compilation fails, guarding the recursive
+    /// descent parser against stack overflows on pathological input.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Keeps the user's parentheses as `Group` nodes in the expression tree so that formatting
+    /// can reproduce them. Has no effect on the compiled program.
+    pub fn set_preserve_groups(&mut self, preserve_groups: bool) {
+        self.preserve_groups = preserve_groups;
+    }
+
+    /// Allows implicit multiplication by juxtaposition (example: '2x'), which is on by default.
+    pub fn set_implicit_multiplication(&mut self, implicit_multiplication: bool) {
+        self.implicit_multiplication = implicit_multiplication;
+    }
+
+    /// Runs the expression tree through `optimizer::optimize` before lowering it.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Drains the warnings raised while compiling (example: a 'let' reassigning an outer variable).
+    /// Like the VM's warnings, they never stop a program from compiling.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
     fn reset(&mut self) -> usize {
         let last_pos = self.position;
         self.position = 0;
@@ -115,95 +272,317 @@ impl<'a> Compiler<'a> {
     pub fn compile(&mut self) -> Result<Vec<Instruction>, String> {
         fn delve(node: &RecursiveExpression, stream: &mut Vec<Instruction>) {
             match node {
+                RecursiveExpression::Sequence(statements) => {
+                    statements.iter().for_each(|node| delve(node, stream));
+                }
                 RecursiveExpression::Block(statements) => {
+                    // a block yields whatever its final statement leaves, so nothing at all if
+                    // that's a 'let'; values left by any earlier statements are discarded
                     stream.push(Instruction::Enter);
-                    statements.iter().for_each(|node| delve(node, stream));
+                    for (idx, node) in statements.iter().enumerate() {
+                        if idx > 0 {
+                            stream.push(Instruction::Discard);
+                        }
+                        delve(node, stream);
+                    }
                     stream.push(Instruction::Leave);
                 }
+                RecursiveExpression::Group(inner) => delve(inner, stream),
                 RecursiveExpression::Literal(x) => stream.push(Instruction::Push(*x)),
+                RecursiveExpression::LiteralInt(x) => stream.push(Instruction::PushInt(*x)),
+                RecursiveExpression::LiteralStr(x) => stream.push(Instruction::PushStr(x.clone())),
+                RecursiveExpression::Array(elements) => {
+                    elements.iter().for_each(|node| delve(node, stream));
+                    stream.push(Instruction::MakeArray(elements.len()));
+                }
+                RecursiveExpression::Tuple(elements) => {
+                    elements.iter().for_each(|node| delve(node, stream));
+                    stream.push(Instruction::MakeTuple(elements.len()));
+                }
+                RecursiveExpression::Range(start, end, inclusive) => {
+                    delve(start, stream);
+                    delve(end, stream);
+                    stream.push(Instruction::Range(*inclusive));
+                }
                 RecursiveExpression::Local(ident) => {
                     stream.push(Instruction::LoadLocal(ident.clone()))
                 }
                 RecursiveExpression::FuncDeclaration(params, body) => {
-                    let mut routine = vec![];
-                    delve(body, &mut routine);
-                    let routine = params
-                        .iter()
-                        .map(|ident| Instruction::ShadowAssign(ident.clone()))
-                        .chain(routine.into_iter())
-                        .collect();
-                    stream.push(Instruction::PushRoutine(routine));
+                    delve_routine(params, body, None, stream)
                 }
                 RecursiveExpression::If(condition, block) => {
                     delve(condition, stream);
                     let mut routine = vec![];
-                    delve(block, &mut routine);
+                    delve_statement_block(block, &mut routine, delve);
                     stream.push(Instruction::SkipIfNot(routine));
                 }
                 RecursiveExpression::IfElse(condition, if_block, else_block) => {
                     delve(condition, stream);
                     let mut if_routine = vec![];
-                    delve(if_block, &mut if_routine);
+                    match else_block.yields_nothing() {
+                        true => delve_statement_block(if_block, &mut if_routine, delve),
+                        false => delve(if_block, &mut if_routine),
+                    }
                     let mut else_routine = vec![];
                     delve(else_block, &mut else_routine);
                     stream.push(Instruction::IfElse(if_routine, else_routine));
                 }
                 RecursiveExpression::AssignOp(ident, value) => {
+                    match value.as_ref() {
+                        RecursiveExpression::FuncDeclaration(params, body) => {
+                            delve_routine(params, body, Some(ident), stream)
+                        }
+                        value => delve(value, stream),
+                    }
+                    stream.push(Instruction::Assign(ident.clone()));
+                }
+                RecursiveExpression::ConstAssign(ident, value) => {
+                    match value.as_ref() {
+                        RecursiveExpression::FuncDeclaration(params, body) => {
+                            delve_routine(params, body, Some(ident), stream)
+                        }
+                        value => delve(value, stream),
+                    }
+                    stream.push(Instruction::AssignConst(ident.clone()));
+                }
+                RecursiveExpression::AssignExpr(ident, value) => {
+                    // assigns a copy, leaving the value itself as the result
                     delve(value, stream);
+                    stream.push(Instruction::Dup);
                     stream.push(Instruction::Assign(ident.clone()));
                 }
+                RecursiveExpression::IndexAssign(ident, index, value) => {
+                    delve(index, stream);
+                    delve(value, stream);
+                    stream.push(Instruction::IndexSet(ident.clone()));
+                }
+                RecursiveExpression::DestructureAssign(idents, value) => {
+                    delve(value, stream);
+                    stream.push(Instruction::Destructure(idents.clone()));
+                }
                 RecursiveExpression::BinaryOp(lhs, op, rhs) => {
                     delve(lhs, stream);
                     delve(rhs, stream);
                     stream.push(op.to_instruction());
                 }
+                RecursiveExpression::Not(value) => {
+                    delve(value, stream);
+                    stream.push(Instruction::Not);
+                }
+                RecursiveExpression::Factorial(value) => {
+                    delve(value, stream);
+                    stream.push(Instruction::Factorial);
+                }
+                RecursiveExpression::Index(target, index) => {
+                    delve(target, stream);
+                    delve(index, stream);
+                    stream.push(Instruction::Index);
+                }
                 RecursiveExpression::Func0(op) => match op {
                     Func0Op::Rand => stream.push(Instruction::PushRandom),
+                    Func0Op::RandNormal => stream.push(Instruction::PushRandomNormal),
                 },
                 RecursiveExpression::Func1(op, value) => {
                     delve(value, stream);
                     match op {
                         Func1Op::Sin => stream.push(Instruction::Sine),
                         Func1Op::Cos => stream.push(Instruction::Cosine),
-                        Func1Op::Sqrt => {
-                            stream.push(Instruction::Push(0.5));
-                            stream.push(Instruction::Pow);
-                        }
+                        Func1Op::Sqrt => stream.push(Instruction::Sqrt),
                         Func1Op::Log => stream.push(Instruction::Log),
                         Func1Op::Round => stream.push(Instruction::Round),
                         Func1Op::Floor => stream.push(Instruction::Floor),
+                        Func1Op::Log2 => stream.push(Instruction::Log2),
+                        Func1Op::Csc => stream.push(Instruction::Csc),
+                        Func1Op::Sec => stream.push(Instruction::Sec),
+                        Func1Op::Cot => stream.push(Instruction::Cot),
+                        Func1Op::Erfc => stream.push(Instruction::Erfc),
+                        Func1Op::Erf => stream.push(Instruction::Erf),
+                        Func1Op::Gamma => stream.push(Instruction::Gamma),
+                        Func1Op::Print => stream.push(Instruction::Print),
+                        Func1Op::Clamp01 => stream.push(Instruction::Clamp01),
                     }
                 }
+                RecursiveExpression::Func2(op, arg1, arg2) => {
+                    delve(arg1, stream);
+                    delve(arg2, stream);
+                    match op {
+                        Func2Op::Map => stream.push(Instruction::Map),
+                        Func2Op::Simulate => stream.push(Instruction::Simulate),
+                        Func2Op::Beta => stream.push(Instruction::Beta),
+                        Func2Op::Pow => stream.push(Instruction::Pow),
+                        Func2Op::RandNormal => stream.push(Instruction::RandomNormal),
+                    }
+                }
+                RecursiveExpression::Func3(op, arg1, arg2, arg3) => {
+                    delve(arg1, stream);
+                    delve(arg2, stream);
+                    delve(arg3, stream);
+                    match op {
+                        Func3Op::Reduce => stream.push(Instruction::Reduce),
+                        Func3Op::Lerp => stream.push(Instruction::Lerp),
+                    }
+                }
+                RecursiveExpression::FuncN(op, args) => {
+                    // a lone argument may already be an array (example: 'max([1, 2])')
+                    match args.as_slice() {
+                        [arg] => delve(arg, stream),
+                        args => {
+                            args.iter().for_each(|node| delve(node, stream));
+                            stream.push(Instruction::MakeArray(args.len()));
+                        }
+                    }
+                    match op {
+                        FuncNOp::Min => stream.push(Instruction::Min),
+                        FuncNOp::Max => stream.push(Instruction::Max),
+                    }
+                }
+                // like builtins, calls push their arguments first-to-last, leaving the last one on
+                // top; a routine's prologue then pops them back off in reverse (see
+                // 'delve_routine')
                 RecursiveExpression::FuncLocal(ident, args) => {
-                    args.iter().rev().for_each(|node| delve(node, stream));
+                    args.iter().for_each(|node| delve(node, stream));
                     stream.push(Instruction::LoadLocal(ident.clone()));
-                    stream.push(Instruction::CallRoutine);
+                    stream.push(Instruction::CallRoutine(ident.clone(), args.len()));
+                }
+                RecursiveExpression::Call(target, args) => {
+                    args.iter().for_each(|node| delve(node, stream));
+                    delve(target, stream);
+                    stream.push(Instruction::CallValue(args.len()));
                 }
             }
         }
 
+        fn delve_routine(
+            params: &[String],
+            body: &RecursiveExpression,
+            name: Option<&str>,
+            stream: &mut Vec<Instruction>,
+        ) {
+            let mut routine = vec![];
+            match name {
+                Some(name) if !params.iter().any(|ident| ident == name) => {
+                    delve_tail(body, name, &mut routine)
+                }
+                _ => delve(body, &mut routine),
+            }
+            // the last argument is on top of the stack, so it is the first to be assigned
+            let routine = params
+                .iter()
+                .rev()
+                .map(|ident| Instruction::ShadowAssign(ident.clone()))
+                .chain(routine)
+                .collect();
+            stream.push(Instruction::PushRoutine(routine));
+        }
+
+        // an 'if' without an 'else' (or an 'else if' chain without a final 'else') is a statement,
+        // so its blocks discard every value they produce and leave the stack the same whichever
+        // branch runs
+        fn delve_statement_block(
+            block: &RecursiveExpression,
+            stream: &mut Vec<Instruction>,
+            delve_last: impl Fn(&RecursiveExpression, &mut Vec<Instruction>),
+        ) {
+            let statements = match block {
+                RecursiveExpression::Block(statements) => statements.iter().collect(),
+                node => vec![node],
+            };
+            stream.push(Instruction::Enter);
+            if let Some((last, statements)) = statements.split_last() {
+                statements.iter().for_each(|node| {
+                    delve(node, stream);
+                    stream.push(Instruction::Discard);
+                });
+                delve_last(last, stream);
+                stream.push(Instruction::Discard);
+            }
+            stream.push(Instruction::Leave);
+        }
+
+        // compiles a node in tail position of the routine bound to `name`, so that calls back
+        // into the same routine can reuse the current frame (example: 'loop(i + 1, n)')
+        fn delve_tail(node: &RecursiveExpression, name: &str, stream: &mut Vec<Instruction>) {
+            match node {
+                RecursiveExpression::Block(statements) => {
+                    stream.push(Instruction::Enter);
+                    if let Some((last, statements)) = statements.split_last() {
+                        statements.iter().for_each(|node| {
+                            delve(node, stream);
+                            stream.push(Instruction::Discard);
+                        });
+                        delve_tail(last, name, stream);
+                    }
+                    stream.push(Instruction::Leave);
+                }
+                RecursiveExpression::If(condition, block) => {
+                    delve(condition, stream);
+                    let mut routine = vec![];
+                    let delve_last = |node: &_, stream: &mut _| delve_tail(node, name, stream);
+                    delve_statement_block(block, &mut routine, delve_last);
+                    stream.push(Instruction::SkipIfNot(routine));
+                }
+                RecursiveExpression::IfElse(condition, if_block, else_block) => {
+                    delve(condition, stream);
+                    let mut if_routine = vec![];
+                    match else_block.yields_nothing() {
+                        true => {
+                            let delve_last =
+                                |node: &_, stream: &mut _| delve_tail(node, name, stream);
+                            delve_statement_block(if_block, &mut if_routine, delve_last)
+                        }
+                        false => delve_tail(if_block, name, &mut if_routine),
+                    }
+                    let mut else_routine = vec![];
+                    delve_tail(else_block, name, &mut else_routine);
+                    stream.push(Instruction::IfElse(if_routine, else_routine));
+                }
+                RecursiveExpression::FuncLocal(ident, args) if ident == name => {
+                    args.iter().for_each(|node| delve(node, stream));
+                    stream.push(Instruction::LoadLocal(ident.clone()));
+                    stream.push(Instruction::TailCallRoutine(args.len()));
+                }
+                node => delve(node, stream),
+            }
+        }
+
         let mut instruction_stream = vec![];
 
         let program_expression = self.compile_expression_tree()?;
+        for ident in crate::resolver::find_shadowing(&program_expression) {
+            let warning = format!("'let {ident}' reassigns a variable from an enclosing scope");
+            self.warnings.push(warning);
+        }
+        let program_expression = match program_expression {
+            expression if self.optimize => crate::optimizer::optimize(expression),
+            expression => expression,
+        };
         delve(&program_expression, &mut instruction_stream);
 
         Ok(instruction_stream)
     }
 
-    pub(crate) fn compile_expression_tree(&mut self) -> Result<RecursiveExpression, String> {
-        let program_expression = self.parse_expression();
+    pub fn compile_expression_tree(&mut self) -> Result<RecursiveExpression, String> {
+        let program_expression = self.parse_statements();
         let last_pos = self.reset();
+        let backtracked = self.backtracked.take();
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
         if last_pos != self.program.len() {
+            // a reverted attempt that got at least as far explains the failure better than
+            // wherever the parse that was kept gave up
+            if let Some((_, err)) = backtracked.filter(|(pos, _)| *pos >= last_pos) {
+                return Err(err);
+            }
+            let remaining: Vec<_> = self.program[last_pos..]
+                .iter()
+                .map(|token| token.to_string())
+                .collect();
+            let remaining = remaining.join(" ");
             let err_msg = if program_expression.is_some() {
-                format!(
-                    "failed to compile remaining tokens: {:?}",
-                    &self.program[last_pos..]
-                )
+                format!("failed to compile remaining tokens: '{remaining}'")
             } else {
-                format!(
-                    "failed to parse expression, unexpected token sequence: {:?}",
-                    &self.program[last_pos..]
-                )
+                format!("failed to parse expression, unexpected token sequence: '{remaining}'")
             };
             return Err(err_msg);
         }
@@ -212,7 +591,41 @@ impl<'a> Compiler<'a> {
         Ok(program_expression)
     }
 
+    fn parse_statements(&mut self) -> Option<RecursiveExpression> {
+        // top-level statements share the caller's scope, unlike a braced block
+        let expression = self.parse_expression()?;
+        let mut statements = vec![expression];
+        while let Some(_) = self.try_consume_separator() {
+            match self.parse_expression() {
+                Some(expression) => statements.push(expression),
+                None => break,
+            }
+        }
+        match statements.len() {
+            1 => statements.pop(),
+            _ => Some(RecursiveExpression::Sequence(statements)),
+        }
+    }
+
     fn parse_expression(&mut self) -> Option<RecursiveExpression> {
+        let start = self.parse_operand_expression()?;
+
+        // ranges bind looser than any binary op (example: '1..n+1')
+        let inclusive = match self.peek() {
+            Some(Token::DotDot) => false,
+            Some(Token::DotDotEq) => true,
+            _ => return Some(start),
+        };
+        self.consume()?;
+        let end = self.parse_operand_expression()?;
+        Some(RecursiveExpression::Range(
+            Box::new(start),
+            Box::new(end),
+            inclusive,
+        ))
+    }
+
+    fn parse_operand_expression(&mut self) -> Option<RecursiveExpression> {
         let expression = self.parse_primary_expression();
 
         match (expression, self.peek_binary_op()) {
@@ -222,19 +635,84 @@ impl<'a> Compiler<'a> {
     }
 
     fn parse_primary_expression(&mut self) -> Option<RecursiveExpression> {
+        if self.depth >= self.max_depth {
+            return self.fail("expression too deeply nested");
+        }
+        self.depth += 1;
+        let expression = self.parse_nested_expression();
+        self.depth -= 1;
+        self.parse_postfix_expression(expression?)
+    }
+
+    fn parse_postfix_expression(
+        &mut self,
+        mut expression: RecursiveExpression,
+    ) -> Option<RecursiveExpression> {
+        loop {
+            match self.peek() {
+                Some(Token::Bang) => {
+                    self.consume()?;
+                    expression = RecursiveExpression::Factorial(Box::new(expression));
+                }
+                // a postfix power (example: 'x²' for 'x^2')
+                Some(&Token::Superscript(exponent)) => {
+                    self.consume()?;
+                    let exponent = RecursiveExpression::Literal(exponent as f64);
+                    expression = RecursiveExpression::BinaryOp(
+                        Box::new(expression),
+                        BinaryOp::Pow,
+                        Box::new(exponent),
+                    );
+                }
+                Some(Token::OpenSquare) => {
+                    self.consume()?;
+                    let index = self.parse_expression()?;
+                    self.try_consume(&Token::CloseSquare)?;
+                    expression = RecursiveExpression::Index(Box::new(expression), Box::new(index));
+                }
+                // only computed callees are called here, elsewhere '(' is implicit multiplication
+                Some(Token::OpenParen)
+                    if matches!(
+                        expression,
+                        RecursiveExpression::Index(..) | RecursiveExpression::Call(..)
+                    ) =>
+                {
+                    self.consume()?;
+                    let args = self.parse_func_argument_list()?;
+                    expression = RecursiveExpression::Call(Box::new(expression), args);
+                }
+                _ => return Some(expression),
+            }
+        }
+    }
+
+    fn parse_nested_expression(&mut self) -> Option<RecursiveExpression> {
         match self.peek() {
             Some(Token::OpenCurly) => self.parse_block(),
+            Some(Token::OpenSquare) => self.parse_array_expression(),
             Some(Token::OpenParen) => self.parse_parens_expression(),
             Some(Token::Let) => self.parse_assignment_expression(),
+            Some(Token::Const) => self.parse_const_assignment(),
             Some(Token::If) => self.parse_if_expression(),
             Some(Token::Pi | Token::E) => self.parse_const_expression(),
-            Some(Token::LiteralNum(_)) => self.parse_literal_expression(),
+            Some(Token::LiteralNum(_) | Token::LiteralInt(_) | Token::LiteralStr(_)) => {
+                self.parse_literal_expression()
+            }
             Some(Token::Identifier(_)) => self.parse_var_expression(),
+            Some(Token::Bang) => self.parse_not_expression(),
             _ => {
-                if let Some(_) = self.peek_func_0_op() {
+                // 'rand_normal' takes either no arguments or two
+                let overloaded = self.peek_func_2_op().is_some() && !self.peek_empty_call();
+                if self.peek_func_0_op().is_some() && !overloaded {
                     self.parse_func_0()
                 } else if let Some(_) = self.peek_func_1_op() {
                     self.parse_func_1()
+                } else if let Some(_) = self.peek_func_2_op() {
+                    self.parse_func_2()
+                } else if let Some(_) = self.peek_func_3_op() {
+                    self.parse_func_3()
+                } else if let Some(_) = self.peek_func_n_op() {
+                    self.parse_func_n()
                 } else if let Some(_) = self.peek_const_literal() {
                     self.parse_const_expression()
                 } else {
@@ -251,15 +729,31 @@ impl<'a> Compiler<'a> {
     }
 
     fn parse_literal_expression(&mut self) -> Option<RecursiveExpression> {
-        match *self.peek()? {
-            Token::LiteralNum(x) => {
+        match self.peek()? {
+            &Token::LiteralNum(x) => {
                 self.consume();
                 Some(RecursiveExpression::Literal(x))
             }
+            &Token::LiteralInt(x) => {
+                self.consume();
+                Some(RecursiveExpression::LiteralInt(x))
+            }
+            Token::LiteralStr(x) => {
+                let x = x.clone();
+                self.consume();
+                Some(RecursiveExpression::LiteralStr(x))
+            }
             _ => None,
         }
     }
 
+    fn parse_array_expression(&mut self) -> Option<RecursiveExpression> {
+        self.try_consume(&Token::OpenSquare)?;
+        let elements =
+            self.parse_separated(Self::parse_expression, &Token::Comma, &Token::CloseSquare)?;
+        Some(RecursiveExpression::Array(elements))
+    }
+
     fn parse_var_expression(&mut self) -> Option<RecursiveExpression> {
         match self.peek()? {
             Token::Identifier(ident) => {
@@ -269,9 +763,20 @@ impl<'a> Compiler<'a> {
                     Some(Token::OpenParen) => {
                         self.consume()?;
                         let args = self.parse_func_argument_list()?;
-                        self.try_consume(&Token::CloseParen)?;
                         Some(RecursiveExpression::FuncLocal(ident, args))
                     }
+                    Some(Token::ColonEquals) => {
+                        self.consume()?;
+                        let value = self.parse_value_expression()?;
+                        Some(RecursiveExpression::AssignExpr(ident, Box::new(value)))
+                    }
+                    Some(Token::OpenSquare) => {
+                        let index_assign = |x: &mut Self| x.parse_index_assignment(&ident);
+                        match self.try_or_revert(index_assign) {
+                            Some(expression) => Some(expression),
+                            None => Some(RecursiveExpression::Local(ident)),
+                        }
+                    }
                     _ => Some(RecursiveExpression::Local(ident)),
                 }
             }
@@ -279,6 +784,25 @@ impl<'a> Compiler<'a> {
         }
     }
 
+    fn parse_index_assignment(&mut self, ident: &str) -> Option<RecursiveExpression> {
+        self.try_consume(&Token::OpenSquare)?;
+        let index = self.parse_expression()?;
+        self.try_consume(&Token::CloseSquare)?;
+        self.try_consume(&Token::Equals)?;
+        let value = self.parse_expression()?;
+        Some(RecursiveExpression::IndexAssign(
+            ident.to_string(),
+            Box::new(index),
+            Box::new(value),
+        ))
+    }
+
+    fn parse_not_expression(&mut self) -> Option<RecursiveExpression> {
+        self.try_consume(&Token::Bang)?;
+        let expression = self.parse_primary_expression()?;
+        Some(RecursiveExpression::Not(Box::new(expression)))
+    }
+
     fn parse_parens_expression(&mut self) -> Option<RecursiveExpression> {
         if let Some(fn_expression) = self.try_or_revert(Self::parse_func_expression) {
             return Some(fn_expression);
@@ -286,8 +810,17 @@ impl<'a> Compiler<'a> {
 
         self.try_consume(&Token::OpenParen)?;
         let expression = self.parse_expression()?;
+        // a comma makes this a tuple (example: '(x, x * x)') rather than a parenthesized expression
+        if let Some(_) = self.try_consume(&Token::Comma) {
+            let mut elements = vec![expression];
+            elements.extend(self.parse_func_argument_list()?);
+            return Some(RecursiveExpression::Tuple(elements));
+        }
         self.try_consume(&Token::CloseParen)?;
-        Some(expression)
+        match self.preserve_groups {
+            true => Some(RecursiveExpression::Group(Box::new(expression))),
+            false => Some(expression),
+        }
     }
 
     fn parse_block(&mut self) -> Option<RecursiveExpression> {
@@ -297,24 +830,49 @@ impl<'a> Compiler<'a> {
         }
         let expression = self.parse_expression()?;
         let mut statements = vec![expression];
-        if let Some(_) = self.try_consume(&Token::Semicolon) {
+        if let Some(_) = self.try_consume_separator() {
             while let Some(expression) = self.parse_expression() {
                 statements.push(expression);
-                if let None = self.try_consume(&Token::Semicolon) {
+                if let None = self.try_consume_separator() {
                     break;
                 }
             }
         }
-        self.try_consume(&Token::CloseCurly)?;
+        if let None = self.try_consume(&Token::CloseCurly) {
+            // anything other than '}' here is the start of a statement missing its separator. a
+            // number followed by a name is read as implicit multiplication instead, so only
+            // 'CompileOptions::implicit_multiplication' set to false catches '{ let x = 1 x + 1 }'
+            return match self.peek() {
+                Some(_) => self.fail("expected ';' between statements"),
+                None => None,
+            };
+        }
         Some(RecursiveExpression::Block(statements))
     }
 
     fn parse_func_expression(&mut self) -> Option<RecursiveExpression> {
+        let start = self.position;
         self.try_consume(&Token::OpenParen)?;
-        let parameters = self.parse_func_params()?;
-        self.try_consume(&Token::CloseParen)?;
+        let Some(parameters) = self.parse_func_params(&Token::CloseParen) else {
+            // only a function if the parentheses are followed by an arrow (example: '(x, 1) => x')
+            self.position = start;
+            if self.skip_parens() && self.try_consume(&Token::LeftArrow).is_some() {
+                return self.fail("function parameters must be names separated by ','");
+            }
+            return None;
+        };
         self.try_consume(&Token::LeftArrow)?;
-        let body = self.parse_expression()?;
+        // only validated once the arrow confirms this is a function (not a parenthesized expr)
+        for (i, param) in parameters.iter().enumerate() {
+            if parameters[..i].contains(param) {
+                return self.fail(&format!("duplicate parameter '{param}'"));
+            }
+        }
+        // past the arrow this can only be a function, so a missing body is an error rather than a
+        // cue to backtrack (a body of several statements needs braces: '(x) => { a; b }')
+        let Some(body) = self.parse_expression() else {
+            return self.fail("expected a function body after '=>', such as '(x) => x + 1'");
+        };
         Some(RecursiveExpression::FuncDeclaration(
             parameters,
             Box::new(body),
@@ -323,15 +881,41 @@ impl<'a> Compiler<'a> {
 
     fn parse_assignment_expression(&mut self) -> Option<RecursiveExpression> {
         self.try_consume(&Token::Let)?;
-        let identifier = match self.peek()? {
-            Token::Identifier(ident) => Some(ident.clone()),
-            _ => None,
-        }?;
-        self.consume()?;
+        let mut identifiers = self.parse_func_params(&Token::Equals)?;
+        let expression = self.parse_value_expression()?;
+        match identifiers.len() {
+            0 => None,
+            1 => Some(RecursiveExpression::AssignOp(
+                identifiers.remove(0),
+                Box::new(expression),
+            )),
+            _ => Some(RecursiveExpression::DestructureAssign(
+                identifiers,
+                Box::new(expression),
+            )),
+        }
+    }
+
+    // parses an expression whose value is used, which an 'if' without an 'else' doesn't have
+    fn parse_value_expression(&mut self) -> Option<RecursiveExpression> {
+        match self.parse_expression()? {
+            expression if expression.yields_nothing() => {
+                self.fail("'if' without 'else' has no value, add an 'else' branch")
+            }
+            expression => Some(expression),
+        }
+    }
+
+    fn parse_const_assignment(&mut self) -> Option<RecursiveExpression> {
+        self.try_consume(&Token::Const)?;
+        let Some(Token::Identifier(ident)) = self.consume() else {
+            return self.fail("expected a name after 'const'");
+        };
+        let ident = ident.clone();
         self.try_consume(&Token::Equals)?;
-        let expression = self.parse_expression()?;
-        Some(RecursiveExpression::AssignOp(
-            identifier,
+        let expression = self.parse_value_expression()?;
+        Some(RecursiveExpression::ConstAssign(
+            ident,
             Box::new(expression),
         ))
     }
@@ -344,7 +928,10 @@ impl<'a> Compiler<'a> {
         let block = self.parse_block()?;
         match self.try_consume(&Token::Else) {
             Some(_) => {
-                let else_block = self.parse_block()?;
+                let else_block = match self.peek() {
+                    Some(Token::If) => self.parse_if_expression()?,
+                    _ => self.parse_block()?,
+                };
                 Some(RecursiveExpression::IfElse(
                     Box::new(expression),
                     Box::new(block),
@@ -375,6 +962,50 @@ impl<'a> Compiler<'a> {
         Some(RecursiveExpression::Func1(func_op, Box::new(expression)))
     }
 
+    fn parse_func_2(&mut self) -> Option<RecursiveExpression> {
+        let func_op = self.peek_func_2_op()?;
+        self.consume()?;
+        self.try_consume(&Token::OpenParen)?;
+        let arg1 = self.parse_expression()?;
+        self.try_consume(&Token::Comma)?;
+        let arg2 = self.parse_expression()?;
+        self.try_consume(&Token::CloseParen)?;
+        Some(RecursiveExpression::Func2(
+            func_op,
+            Box::new(arg1),
+            Box::new(arg2),
+        ))
+    }
+
+    fn parse_func_3(&mut self) -> Option<RecursiveExpression> {
+        let func_op = self.peek_func_3_op()?;
+        self.consume()?;
+        self.try_consume(&Token::OpenParen)?;
+        let arg1 = self.parse_expression()?;
+        self.try_consume(&Token::Comma)?;
+        let arg2 = self.parse_expression()?;
+        self.try_consume(&Token::Comma)?;
+        let arg3 = self.parse_expression()?;
+        self.try_consume(&Token::CloseParen)?;
+        Some(RecursiveExpression::Func3(
+            func_op,
+            Box::new(arg1),
+            Box::new(arg2),
+            Box::new(arg3),
+        ))
+    }
+
+    fn parse_func_n(&mut self) -> Option<RecursiveExpression> {
+        let func_op = self.peek_func_n_op()?;
+        self.consume()?;
+        self.try_consume(&Token::OpenParen)?;
+        let args = self.parse_func_argument_list()?;
+        if args.is_empty() {
+            return self.fail("expected at least one argument");
+        }
+        Some(RecursiveExpression::FuncN(func_op, args))
+    }
+
     fn parse_binary_op(
         &mut self,
         mut lhs: RecursiveExpression,
@@ -384,14 +1015,23 @@ impl<'a> Compiler<'a> {
             .peek_binary_op()
             .filter(|op| op.precedence() >= min_precedence)
         {
+            let after_literal = matches!(
+                lhs,
+                RecursiveExpression::Literal(_) | RecursiveExpression::LiteralInt(_)
+            );
             match self.peek() {
                 Some(Token::OpenParen) => {
                     // handles implicit multiplication by parentheses (example: '(x+1)(x-2)')
                 }
-                Some(Token::Identifier(_)) if matches!(lhs, RecursiveExpression::Literal(_)) => {
+                Some(Token::Identifier(_)) if after_literal => {
                     // handles implicit multiplication by literal (example: '3x')
                 }
-                Some(Token::Identifier(_)) => return None, // otherwise, token was not expected
+                _ if after_literal && self.peek_func_keyword() => {
+                    // handles implicit multiplication by a function call (example: '2sin(90)')
+                }
+                // otherwise, token was not expected
+                Some(Token::Identifier(_)) => return None,
+                _ if self.peek_func_keyword() => return None,
                 _ => {
                     self.consume()?;
                 }
@@ -399,11 +1039,16 @@ impl<'a> Compiler<'a> {
 
             let mut rhs = self.parse_primary_expression()?;
 
+            // powers are right-associative (example: '2^3^2' is '2^(3^2)')
+            let min_precedence = match op {
+                BinaryOp::Pow => op.precedence(),
+                _ => op.precedence() + 1,
+            };
             while let Some(_) = self
                 .peek_binary_op()
-                .filter(|next_op| next_op.precedence() > op.precedence())
+                .filter(|next_op| next_op.precedence() >= min_precedence)
             {
-                rhs = self.parse_binary_op(rhs, op.precedence() + 1)?;
+                rhs = self.parse_binary_op(rhs, min_precedence)?;
             }
 
             lhs = RecursiveExpression::BinaryOp(Box::new(lhs), op, Box::new(rhs))
@@ -412,27 +1057,45 @@ impl<'a> Compiler<'a> {
         Some(lhs)
     }
 
-    fn parse_func_params(&mut self) -> Option<Vec<String>> {
-        let mut idents = vec![];
-        while let Some(Token::Identifier(ident)) = self.peek() {
-            idents.push(ident.clone());
-            self.consume()?;
-            if let None = self.try_consume(&Token::Comma) {
-                break;
+    // parses 'item (sep item)* sep?' up to and including the closing token, so an empty list and a
+    // trailing separator are both accepted (examples: '()', '(a, b,)')
+    pub fn parse_separated<T>(
+        &mut self,
+        item: impl Fn(&mut Self) -> Option<T>,
+        sep: &Token,
+        close: &Token,
+    ) -> Option<Vec<T>> {
+        let mut items = vec![];
+        loop {
+            if let Some(_) = self.try_consume(close) {
+                return Some(items);
+            }
+            items.push(item(self)?);
+            if let None = self.try_consume(sep) {
+                self.try_consume(close)?;
+                return Some(items);
             }
         }
-        Some(idents)
     }
 
-    fn parse_func_argument_list(&mut self) -> Option<Vec<RecursiveExpression>> {
-        let mut idents = vec![];
-        while let Some(expression) = self.parse_expression() {
-            idents.push(expression);
-            if let None = self.try_consume(&Token::Comma) {
-                break;
+    fn parse_func_params(&mut self, close: &Token) -> Option<Vec<String>> {
+        self.parse_separated(Self::parse_param, &Token::Comma, close)
+    }
+
+    fn parse_param(&mut self) -> Option<String> {
+        match self.peek()? {
+            Token::Identifier(ident) => {
+                let ident = ident.clone();
+                self.consume()?;
+                Some(ident)
             }
+            _ => None,
         }
-        Some(idents)
+    }
+
+    // the arguments of a call, after its '(' and up to and including the ')'
+    fn parse_func_argument_list(&mut self) -> Option<Vec<RecursiveExpression>> {
+        self.parse_separated(Self::parse_expression, &Token::Comma, &Token::CloseParen)
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -443,39 +1106,54 @@ impl<'a> Compiler<'a> {
         let token = self.peek()?;
         match BinaryOp::from_token(token) {
             Some(op) => Some(op),
+            _ if !self.implicit_multiplication => None,
             _ => match token {
                 Token::OpenParen => Some(BinaryOp::Mul),
                 Token::Identifier(_) => Some(BinaryOp::Mul),
+                _ if self.peek_func_keyword() => Some(BinaryOp::Mul),
                 _ => None,
             },
         }
     }
 
-    fn peek_func_0_op(&mut self) -> Option<Func0Op> {
-        match self.peek()? {
-            Token::Rand => Some(Func0Op::Rand),
-            _ => None,
-        }
+    fn peek_func_keyword(&self) -> bool {
+        self.peek_func_0_op().is_some()
+            || self.peek_func_1_op().is_some()
+            || self.peek_func_2_op().is_some()
+            || self.peek_func_3_op().is_some()
+            || self.peek_func_n_op().is_some()
     }
 
-    fn peek_func_1_op(&mut self) -> Option<Func1Op> {
-        match self.peek()? {
-            Token::Sine => Some(Func1Op::Sin),
-            Token::Cosine => Some(Func1Op::Cos),
-            Token::Sqrt => Some(Func1Op::Sqrt),
-            Token::Log => Some(Func1Op::Log),
-            Token::Round => Some(Func1Op::Round),
-            Token::Floor => Some(Func1Op::Floor),
-            _ => None,
-        }
+    fn peek_func_0_op(&self) -> Option<Func0Op> {
+        func_0_op(self.peek()?)
+    }
+
+    fn peek_func_1_op(&self) -> Option<Func1Op> {
+        func_1_op(self.peek()?)
+    }
+
+    fn peek_func_2_op(&self) -> Option<Func2Op> {
+        func_2_op(self.peek()?)
+    }
+
+    fn peek_func_3_op(&self) -> Option<Func3Op> {
+        func_3_op(self.peek()?)
+    }
+
+    fn peek_func_n_op(&self) -> Option<FuncNOp> {
+        func_n_op(self.peek()?)
+    }
+
+    // whether the next tokens are a function name followed by '()'
+    fn peek_empty_call(&self) -> bool {
+        matches!(
+            self.program.get(self.position + 1..self.position + 3),
+            Some([Token::OpenParen, Token::CloseParen])
+        )
     }
 
     fn peek_const_literal(&mut self) -> Option<f64> {
-        match self.peek()? {
-            Token::Pi => Some(std::f64::consts::PI),
-            Token::E => Some(std::f64::consts::E),
-            _ => None,
-        }
+        const_literal(self.peek()?)
     }
 
     fn consume(&mut self) -> Option<&Token> {
@@ -494,15 +1172,159 @@ impl<'a> Compiler<'a> {
         }
     }
 
+    // newline tokens are only emitted when the input was lexed with newlines as separators
+    fn try_consume_separator(&mut self) -> Option<&Token> {
+        match self.peek()? {
+            Token::Semicolon | Token::Newline => self.consume(),
+            _ => None,
+        }
+    }
+
+    fn fail<T>(&mut self, message: &str) -> Option<T> {
+        // the first failure is the root cause, anything after is fallout from unwinding
+        self.error.get_or_insert_with(|| message.to_string());
+        None
+    }
+
+    // moves past the parenthesized group starting at the current token, returning false if it
+    // isn't one or is never closed
+    fn skip_parens(&mut self) -> bool {
+        let mut depth = 0;
+        while let Some(token) = self.consume() {
+            match token {
+                Token::OpenParen => depth += 1,
+                Token::CloseParen if depth <= 1 => return depth == 1,
+                Token::CloseParen => depth -= 1,
+                _ if depth == 0 => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
     fn try_or_revert(
         &mut self,
         mut parse_fn: impl FnMut(&mut Self) -> Option<RecursiveExpression>,
     ) -> Option<RecursiveExpression> {
         let initial_position = self.position;
+        // errors raised by the attempt only count if nothing else parses either
+        let outer_error = self.error.take();
         let parse_result = parse_fn(self);
+        let error = std::mem::replace(&mut self.error, outer_error);
+        match (&parse_result, error) {
+            (Some(_), Some(err)) => {
+                self.error.get_or_insert(err);
+            }
+            (None, Some(err)) => {
+                let reached = self.position;
+                if self
+                    .backtracked
+                    .as_ref()
+                    .map_or(true, |(pos, _)| reached >= *pos)
+                {
+                    self.backtracked = Some((reached, err));
+                }
+            }
+            _ => {}
+        }
         if parse_result.is_none() {
             self.position = initial_position;
         }
         parse_result
     }
 }
+
+// the builtin functions and constants that have keywords of their own (natives don't)
+pub(crate) fn keyword_builtins() -> Builtins {
+    let mut builtins = Builtins::default();
+    for token in crate::lexer::BUILTIN_TOKENS {
+        if let Some(function) = builtin_function(token) {
+            builtins.functions.push(function);
+        } else if const_literal(token).is_some() {
+            builtins.constants.push(token.to_string());
+        }
+    }
+    builtins
+}
+
+// describes the builtin function that `token` names, if it is one
+fn builtin_function(token: &Token) -> Option<BuiltinFunction> {
+    let arities = [
+        func_0_op(token).map(|_| 0),
+        func_1_op(token).map(|_| 1),
+        func_2_op(token).map(|_| 2),
+        func_3_op(token).map(|_| 3),
+    ];
+    let arities: Vec<_> = arities.into_iter().flatten().collect();
+    if arities.is_empty() && func_n_op(token).is_none() {
+        return None;
+    }
+    Some(BuiltinFunction {
+        name: token.to_string(),
+        arities,
+    })
+}
+
+fn func_0_op(token: &Token) -> Option<Func0Op> {
+    match token {
+        Token::Rand => Some(Func0Op::Rand),
+        Token::RandNormal => Some(Func0Op::RandNormal),
+        _ => None,
+    }
+}
+
+fn func_1_op(token: &Token) -> Option<Func1Op> {
+    match token {
+        Token::Sine => Some(Func1Op::Sin),
+        Token::Cosine => Some(Func1Op::Cos),
+        Token::Sqrt => Some(Func1Op::Sqrt),
+        Token::Log => Some(Func1Op::Log),
+        Token::Round => Some(Func1Op::Round),
+        Token::Floor => Some(Func1Op::Floor),
+        Token::Log2 => Some(Func1Op::Log2),
+        Token::Csc => Some(Func1Op::Csc),
+        Token::Sec => Some(Func1Op::Sec),
+        Token::Cot => Some(Func1Op::Cot),
+        Token::Erfc => Some(Func1Op::Erfc),
+        Token::Erf => Some(Func1Op::Erf),
+        Token::Gamma => Some(Func1Op::Gamma),
+        Token::Print => Some(Func1Op::Print),
+        Token::Clamp01 => Some(Func1Op::Clamp01),
+        _ => None,
+    }
+}
+
+fn func_2_op(token: &Token) -> Option<Func2Op> {
+    match token {
+        Token::Map => Some(Func2Op::Map),
+        Token::Simulate => Some(Func2Op::Simulate),
+        Token::Beta => Some(Func2Op::Beta),
+        Token::Power => Some(Func2Op::Pow),
+        Token::RandNormal => Some(Func2Op::RandNormal),
+        _ => None,
+    }
+}
+
+fn func_3_op(token: &Token) -> Option<Func3Op> {
+    match token {
+        Token::Reduce => Some(Func3Op::Reduce),
+        Token::Lerp => Some(Func3Op::Lerp),
+        _ => None,
+    }
+}
+
+fn func_n_op(token: &Token) -> Option<FuncNOp> {
+    match token {
+        Token::Min => Some(FuncNOp::Min),
+        Token::Max => Some(FuncNOp::Max),
+        _ => None,
+    }
+}
+
+pub(crate) fn const_literal(token: &Token) -> Option<f64> {
+    match token {
+        Token::Pi => Some(std::f64::consts::PI),
+        Token::E => Some(std::f64::consts::E),
+        _ => None,
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    compiler::{BinaryOp, Func0Op, Func1Op, Func2Op, Func3Op, FuncNOp, RecursiveExpression},
+    vm::Instruction,
+};
+
+// rebuilds an expression tree by replaying the program against a stack of expressions instead of
+// values, i.e. the inverse of the compiler's 'delve'
+pub fn decompile(program: &[Instruction]) -> Result<RecursiveExpression, String> {
+    let mut stack = vec![];
+    // stack heights at each open block, so its statements can be gathered again on 'Leave'
+    let mut blocks = vec![];
+    let mut instructions = program.iter().peekable();
+    while let Some(instruction) = instructions.next() {
+        match instruction {
+            Instruction::Enter => blocks.push(stack.len()),
+            // the compiler only duplicates a value to assign it while keeping it as the result
+            Instruction::Dup if matches!(instructions.peek(), Some(Instruction::Assign(_))) => {
+                let Some(Instruction::Assign(ident)) = instructions.next() else {
+                    unreachable!()
+                };
+                let value = pop(&mut stack)?;
+                stack.push(RecursiveExpression::AssignExpr(
+                    ident.clone(),
+                    Box::new(value),
+                ));
+            }
+            // only separates statements, which the expression stack already keeps apart
+            Instruction::Discard => {}
+            Instruction::Leave => {
+                let start = blocks
+                    .pop()
+                    .ok_or_else(|| String::from("unbalanced block"))?;
+                let statements = stack.split_off(start);
+                stack.push(RecursiveExpression::Block(statements));
+            }
+            instruction => decompile_instruction(instruction, &mut stack)?,
+        }
+    }
+    if !blocks.is_empty() {
+        return Err(String::from("unbalanced block"));
+    }
+    match stack.len() {
+        0 => Err(String::from("nothing to decompile")),
+        1 => Ok(stack.pop().unwrap()),
+        _ => Ok(RecursiveExpression::Sequence(stack)),
+    }
+}
+
+// splits a routine into the parameters it binds on entry and the body that follows
+pub(crate) fn decompile_routine(
+    routine: &[Instruction],
+) -> Result<(Vec<String>, RecursiveExpression), String> {
+    let mut params: Vec<_> = routine
+        .iter()
+        .map_while(|instruction| match instruction {
+            Instruction::ShadowAssign(ident) => Some(ident.clone()),
+            _ => None,
+        })
+        .collect();
+    let body = decompile(&routine[params.len()..])?;
+    // parameters are assigned last-to-first
+    params.reverse();
+    Ok((params, body))
+}
+
+fn decompile_instruction(
+    instruction: &Instruction,
+    stack: &mut Vec<RecursiveExpression>,
+) -> Result<(), String> {
+    let expression = match instruction {
+        Instruction::Push(x) => RecursiveExpression::Literal(*x),
+        Instruction::PushInt(x) => RecursiveExpression::LiteralInt(*x),
+        Instruction::PushStr(x) => RecursiveExpression::LiteralStr(x.clone()),
+        Instruction::LoadLocal(ident) => RecursiveExpression::Local(ident.clone()),
+        Instruction::PushRandom => RecursiveExpression::Func0(Func0Op::Rand),
+        Instruction::PushRandomNormal => RecursiveExpression::Func0(Func0Op::RandNormal),
+        Instruction::MakeArray(len) => {
+            let start = stack
+                .len()
+                .checked_sub(*len)
+                .ok_or_else(|| String::from("missing array elements"))?;
+            RecursiveExpression::Array(stack.split_off(start))
+        }
+        Instruction::MakeTuple(len) => {
+            let start = stack
+                .len()
+                .checked_sub(*len)
+                .ok_or_else(|| String::from("missing tuple elements"))?;
+            RecursiveExpression::Tuple(stack.split_off(start))
+        }
+        Instruction::Destructure(idents) => {
+            RecursiveExpression::DestructureAssign(idents.clone(), Box::new(pop(stack)?))
+        }
+        Instruction::Range(inclusive) => {
+            let end = pop(stack)?;
+            let start = pop(stack)?;
+            RecursiveExpression::Range(Box::new(start), Box::new(end), *inclusive)
+        }
+        Instruction::Add => binary_op(stack, BinaryOp::Add)?,
+        Instruction::Sub => binary_op(stack, BinaryOp::Sub)?,
+        Instruction::Mul => binary_op(stack, BinaryOp::Mul)?,
+        Instruction::Div => binary_op(stack, BinaryOp::Div)?,
+        Instruction::Mod => binary_op(stack, BinaryOp::Mod)?,
+        Instruction::Pow => binary_op(stack, BinaryOp::Pow)?,
+        Instruction::CmpEQ => binary_op(stack, BinaryOp::EQ)?,
+        Instruction::CmpNEQ => binary_op(stack, BinaryOp::NEQ)?,
+        Instruction::CmpLT => binary_op(stack, BinaryOp::LT)?,
+        Instruction::CmpLTE => binary_op(stack, BinaryOp::LTE)?,
+        Instruction::CmpGT => binary_op(stack, BinaryOp::GT)?,
+        Instruction::CmpGTE => binary_op(stack, BinaryOp::GTE)?,
+        Instruction::Sine => func_1(stack, Func1Op::Sin)?,
+        Instruction::Cosine => func_1(stack, Func1Op::Cos)?,
+        Instruction::Sqrt => func_1(stack, Func1Op::Sqrt)?,
+        Instruction::Log => func_1(stack, Func1Op::Log)?,
+        Instruction::Round => func_1(stack, Func1Op::Round)?,
+        Instruction::Floor => func_1(stack, Func1Op::Floor)?,
+        Instruction::Log2 => func_1(stack, Func1Op::Log2)?,
+        Instruction::Csc => func_1(stack, Func1Op::Csc)?,
+        Instruction::Sec => func_1(stack, Func1Op::Sec)?,
+        Instruction::Cot => func_1(stack, Func1Op::Cot)?,
+        Instruction::Erfc => func_1(stack, Func1Op::Erfc)?,
+        Instruction::Erf => func_1(stack, Func1Op::Erf)?,
+        Instruction::Gamma => func_1(stack, Func1Op::Gamma)?,
+        Instruction::Print => func_1(stack, Func1Op::Print)?,
+        Instruction::Clamp01 => func_1(stack, Func1Op::Clamp01)?,
+        Instruction::Not => RecursiveExpression::Not(Box::new(pop(stack)?)),
+        Instruction::Factorial => RecursiveExpression::Factorial(Box::new(pop(stack)?)),
+        Instruction::Index => {
+            let index = pop(stack)?;
+            let target = pop(stack)?;
+            RecursiveExpression::Index(Box::new(target), Box::new(index))
+        }
+        Instruction::IndexSet(ident) => {
+            let value = pop(stack)?;
+            let index = pop(stack)?;
+            RecursiveExpression::IndexAssign(ident.clone(), Box::new(index), Box::new(value))
+        }
+        Instruction::Map => func_2(stack, Func2Op::Map)?,
+        Instruction::Simulate => func_2(stack, Func2Op::Simulate)?,
+        Instruction::Beta => func_2(stack, Func2Op::Beta)?,
+        Instruction::RandomNormal => func_2(stack, Func2Op::RandNormal)?,
+        Instruction::Reduce => func_3(stack, Func3Op::Reduce)?,
+        Instruction::Lerp => func_3(stack, Func3Op::Lerp)?,
+        Instruction::CallRoutine(_, arity) | Instruction::TailCallRoutine(arity) => {
+            let RecursiveExpression::Local(ident) = pop(stack)? else {
+                return Err(String::from("can only decompile calls to named functions"));
+            };
+            RecursiveExpression::FuncLocal(ident, pop_args(stack, *arity)?)
+        }
+        Instruction::Min => func_n(stack, FuncNOp::Min)?,
+        Instruction::Max => func_n(stack, FuncNOp::Max)?,
+        Instruction::CallValue(arity) => {
+            let target = pop(stack)?;
+            RecursiveExpression::Call(Box::new(target), pop_args(stack, *arity)?)
+        }
+        Instruction::PushRoutine(routine) => {
+            let (params, body) = decompile_routine(routine)?;
+            RecursiveExpression::FuncDeclaration(params, Box::new(body))
+        }
+        Instruction::SkipIfNot(block) => {
+            let condition = pop(stack)?;
+            RecursiveExpression::If(Box::new(condition), Box::new(decompile(block)?))
+        }
+        Instruction::IfElse(if_block, else_block) => {
+            let condition = pop(stack)?;
+            RecursiveExpression::IfElse(
+                Box::new(condition),
+                Box::new(decompile(if_block)?),
+                Box::new(decompile(else_block)?),
+            )
+        }
+        Instruction::Swap => {
+            let top = pop(stack)?;
+            let below = pop(stack)?;
+            stack.push(top);
+            below
+        }
+        Instruction::Assign(ident) => {
+            RecursiveExpression::AssignOp(ident.clone(), Box::new(pop(stack)?))
+        }
+        Instruction::AssignConst(ident) => {
+            RecursiveExpression::ConstAssign(ident.clone(), Box::new(pop(stack)?))
+        }
+        instruction => return Err(format!("cannot decompile instruction {instruction:?}")),
+    };
+    stack.push(expression);
+    Ok(())
+}
+
+fn binary_op(
+    stack: &mut Vec<RecursiveExpression>,
+    op: BinaryOp,
+) -> Result<RecursiveExpression, String> {
+    let rhs = pop(stack)?;
+    let lhs = pop(stack)?;
+    Ok(RecursiveExpression::BinaryOp(
+        Box::new(lhs),
+        op,
+        Box::new(rhs),
+    ))
+}
+
+fn func_1(
+    stack: &mut Vec<RecursiveExpression>,
+    op: Func1Op,
+) -> Result<RecursiveExpression, String> {
+    Ok(RecursiveExpression::Func1(op, Box::new(pop(stack)?)))
+}
+
+fn func_2(
+    stack: &mut Vec<RecursiveExpression>,
+    op: Func2Op,
+) -> Result<RecursiveExpression, String> {
+    let arg2 = pop(stack)?;
+    let arg1 = pop(stack)?;
+    Ok(RecursiveExpression::Func2(
+        op,
+        Box::new(arg1),
+        Box::new(arg2),
+    ))
+}
+
+fn func_3(
+    stack: &mut Vec<RecursiveExpression>,
+    op: Func3Op,
+) -> Result<RecursiveExpression, String> {
+    let arg3 = pop(stack)?;
+    let arg2 = pop(stack)?;
+    let arg1 = pop(stack)?;
+    Ok(RecursiveExpression::Func3(
+        op,
+        Box::new(arg1),
+        Box::new(arg2),
+        Box::new(arg3),
+    ))
+}
+
+// 'min(a, b)' and 'min([a, b])' compile alike, so the array form decompiles to the argument list
+fn func_n(
+    stack: &mut Vec<RecursiveExpression>,
+    op: FuncNOp,
+) -> Result<RecursiveExpression, String> {
+    let args = match pop(stack)? {
+        RecursiveExpression::Array(elements) if !elements.is_empty() => elements,
+        arg => vec![arg],
+    };
+    Ok(RecursiveExpression::FuncN(op, args))
+}
+
+fn pop_args(
+    stack: &mut Vec<RecursiveExpression>,
+    arity: usize,
+) -> Result<Vec<RecursiveExpression>, String> {
+    let start = stack
+        .len()
+        .checked_sub(arity)
+        .ok_or_else(|| String::from("missing argument"))?;
+    Ok(stack.split_off(start))
+}
+
+fn pop(stack: &mut Vec<RecursiveExpression>) -> Result<RecursiveExpression, String> {
+    stack.pop().ok_or_else(|| String::from("missing operand"))
+}
@@ -1,10 +1,12 @@
-use std::{fmt::Display, str::FromStr};
+use std::{fmt::Display, ops::Range};
 
 use crate::parser;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     LiteralNum(f64),
+    LiteralInt(i64),
+    LiteralStr(String),
     Plus,
     Sub,
     Mul,
@@ -14,15 +16,36 @@ pub enum Token {
     Log,
     Round,
     Floor,
+    Log2,
+    Csc,
+    Sec,
+    Cot,
+    Erfc,
+    Erf,
+    Gamma,
+    Print,
+    Clamp01,
     OpenParen,
     CloseParen,
     OpenCurly,
     CloseCurly,
+    OpenSquare,
+    CloseSquare,
     Pow,
     Mod,
     Rand,
+    RandNormal,
+    Map,
+    Simulate,
+    Min,
+    Max,
+    Beta,
+    Power,
+    Reduce,
+    Lerp,
     Identifier(String),
     Let,
+    Const,
     If,
     Else,
     LeftArrow,
@@ -31,6 +54,7 @@ pub enum Token {
     GreaterThan,
     GreaterThanEquals,
     Equals,
+    ColonEquals,
     Pi,
     E,
     Sqrt,
@@ -38,34 +62,388 @@ pub enum Token {
     Semicolon,
     Eq,
     NotEq,
+    Bang,
+    DotDot,
+    DotDotEq,
+    Superscript(u32),
+    Newline,
 }
 
-pub fn tokenize<'a>(source: parser::Bite<'a>) -> impl Iterator<Item = Result<Token, String>> + 'a {
+// renders the token as written in source, with the newline token escaped to stay on one line
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self {
+            Token::LiteralNum(x) => return write!(f, "{x}"),
+            Token::LiteralInt(x) => return write!(f, "{x}"),
+            Token::LiteralStr(x) => return write!(f, "\"{x}\""),
+            Token::Identifier(ident) => ident,
+            Token::Superscript(x) => {
+                let digits = x.to_string();
+                let digits = digits
+                    .chars()
+                    .map(|c| parser::SUPERSCRIPT_DIGITS[c as usize - '0' as usize]);
+                return f.write_str(&digits.collect::<String>());
+            }
+            Token::Plus => "+",
+            Token::Sub => "-",
+            Token::Mul => "*",
+            Token::Div => "/",
+            Token::Sine => "sin",
+            Token::Cosine => "cos",
+            Token::Log => "log",
+            Token::Round => "round",
+            Token::Floor => "floor",
+            Token::Log2 => "log2",
+            Token::Csc => "csc",
+            Token::Sec => "sec",
+            Token::Cot => "cot",
+            Token::Erfc => "erfc",
+            Token::Erf => "erf",
+            Token::Gamma => "gamma",
+            Token::Print => "print",
+            Token::Clamp01 => "clamp01",
+            Token::OpenParen => "(",
+            Token::CloseParen => ")",
+            Token::OpenCurly => "{",
+            Token::CloseCurly => "}",
+            Token::OpenSquare => "[",
+            Token::CloseSquare => "]",
+            Token::Pow => "^",
+            Token::Mod => "%",
+            Token::Rand => "rand",
+            Token::RandNormal => "rand_normal",
+            Token::Map => "map",
+            Token::Simulate => "simulate",
+            Token::Min => "min",
+            Token::Max => "max",
+            Token::Beta => "beta",
+            Token::Power => "pow",
+            Token::Reduce => "reduce",
+            Token::Lerp => "lerp",
+            Token::Let => "let",
+            Token::Const => "const",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::LeftArrow => "=>",
+            Token::LessThan => "<",
+            Token::LessThanEquals => "<=",
+            Token::GreaterThan => ">",
+            Token::GreaterThanEquals => ">=",
+            Token::Equals => "=",
+            Token::ColonEquals => ":=",
+            Token::Pi => "pi",
+            Token::E => "E",
+            Token::Sqrt => "sqrt",
+            Token::Comma => ",",
+            Token::Semicolon => ";",
+            Token::Eq => "==",
+            Token::NotEq => "!=",
+            Token::Bang => "!",
+            Token::DotDot => "..",
+            Token::DotDotEq => "..=",
+            Token::Newline => "\\n",
+        };
+        f.write_str(source)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LexErrorKind {
+    UnterminatedString,
+    UnknownChar,
+    BadNumber,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LexError {
+    /// Byte offset of the offending token from the start of the tokenized input.
+    pub offset: usize,
+    pub kind: LexErrorKind,
+    /// The malformed number, or the rest of the input from the point lexing failed.
+    pub snippet: String,
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let snippet = &self.snippet;
+        match self.kind {
+            LexErrorKind::UnterminatedString => write!(f, "Unterminated string literal: {snippet}"),
+            LexErrorKind::UnknownChar => write!(f, "Could not parse: {snippet}"),
+            // the only way f64 parsing can fail on a non-empty literal
+            LexErrorKind::BadNumber => {
+                write!(f, "Could not parse '{snippet}': invalid float literal")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TokenClass {
+    Number,
+    Str,
+    Operator,
+    Keyword,
+    Identifier,
+    Function,
+    Paren,
+}
+
+pub fn classify(token: &Token) -> TokenClass {
+    match token {
+        Token::LiteralNum(_) | Token::LiteralInt(_) | Token::Pi | Token::E => TokenClass::Number,
+        Token::LiteralStr(_) => TokenClass::Str,
+        Token::Identifier(_) => TokenClass::Identifier,
+        Token::Let | Token::Const | Token::If | Token::Else => TokenClass::Keyword,
+        Token::Sine
+        | Token::Cosine
+        | Token::Log
+        | Token::Round
+        | Token::Floor
+        | Token::Log2
+        | Token::Csc
+        | Token::Sec
+        | Token::Cot
+        | Token::Erfc
+        | Token::Erf
+        | Token::Gamma
+        | Token::Print
+        | Token::Clamp01
+        | Token::Sqrt
+        | Token::Rand
+        | Token::RandNormal
+        | Token::Map
+        | Token::Simulate
+        | Token::Min
+        | Token::Max
+        | Token::Beta
+        | Token::Power
+        | Token::Reduce
+        | Token::Lerp => TokenClass::Function,
+        Token::OpenParen
+        | Token::CloseParen
+        | Token::OpenCurly
+        | Token::CloseCurly
+        | Token::OpenSquare
+        | Token::CloseSquare => TokenClass::Paren,
+        Token::Plus
+        | Token::Sub
+        | Token::Mul
+        | Token::Div
+        | Token::Pow
+        | Token::Mod
+        | Token::LeftArrow
+        | Token::LessThan
+        | Token::LessThanEquals
+        | Token::GreaterThan
+        | Token::GreaterThanEquals
+        | Token::Equals
+        | Token::ColonEquals
+        | Token::Comma
+        | Token::Semicolon
+        | Token::Newline
+        | Token::Eq
+        | Token::NotEq
+        | Token::Bang
+        | Token::DotDot
+        | Token::DotDotEq
+        | Token::Superscript(_) => TokenClass::Operator,
+    }
+}
+
+// every keyword naming a builtin function or constant
+pub(crate) const BUILTIN_TOKENS: &[Token] = &[
+    Token::Sine,
+    Token::Cosine,
+    Token::Log,
+    Token::Round,
+    Token::Floor,
+    Token::Log2,
+    Token::Csc,
+    Token::Sec,
+    Token::Cot,
+    Token::Erfc,
+    Token::Erf,
+    Token::Gamma,
+    Token::Print,
+    Token::Clamp01,
+    Token::Sqrt,
+    Token::Rand,
+    Token::RandNormal,
+    Token::Map,
+    Token::Simulate,
+    Token::Min,
+    Token::Max,
+    Token::Beta,
+    Token::Power,
+    Token::Reduce,
+    Token::Lerp,
+    Token::Pi,
+    Token::E,
+];
+
+pub fn tokenize<'a>(
+    source: parser::Bite<'a>,
+) -> impl Iterator<Item = Result<Token, LexError>> + 'a {
+    tokenize_spanned(source).map(|x| x.map(|(_, token)| token))
+}
+
+/// Like `tokenize`, but pairs each token with its byte range relative to the start of `source`.
+/// Tokens appended to auto-close open brackets have an empty range at the end of the input.
+pub fn tokenize_spanned<'a>(
+    source: parser::Bite<'a>,
+) -> impl Iterator<Item = Result<(Range<usize>, Token), LexError>> + 'a {
+    tokenize_spanned_impl(source, Default::default(), &NO_ALIASES)
+}
+
+/// Like `tokenize`, but also recognizes the extra spellings registered in `aliases`.
+pub fn tokenize_with_aliases<'a>(
+    source: parser::Bite<'a>,
+    aliases: &'a AliasTable,
+) -> impl Iterator<Item = Result<Token, LexError>> + 'a {
+    tokenize_spanned_impl(source, Default::default(), aliases).map(|x| x.map(|(_, token)| token))
+}
+
+static NO_ALIASES: AliasTable = AliasTable { aliases: vec![] };
+
+/// Additional spellings for existing tokens (examples: a localized function name, or a symbol
+/// from a phone keyboard), checked before any of the builtin spellings.
+#[derive(Debug, Default, Clone)]
+pub struct AliasTable {
+    aliases: Vec<(String, Token)>,
+}
+
+impl AliasTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lexes `alias` as `token`. Where several aliases match, the longest one wins.
+    pub fn insert(&mut self, alias: &str, token: Token) {
+        self.aliases.retain(|(existing, _)| existing != alias);
+        self.aliases.push((alias.to_string(), token));
+        self.aliases
+            .sort_by_key(|(alias, _)| std::cmp::Reverse(alias.len()));
+    }
+
+    fn nibble(&self, bite: &mut parser::Bite<'_>) -> Option<Token> {
+        self.aliases.iter().find_map(|(alias, token)| {
+            let matcher = |x: &str| x.starts_with(alias.as_str()).then(|| alias.len());
+            bite.nibble(parser::Chomp::new(matcher))
+                .map(|_| token.clone())
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LexOptions {
+    /// Emits a `Newline` token wherever a line break separates two statements. Line breaks that
+    /// can't end a statement, such as after an operator or before a closing bracket, are skipped
+    /// so expressions can still span several lines.
+    pub newline_separators: bool,
+    /// Emits `LiteralInt` rather than `LiteralNum` for numbers written without a decimal point.
+    pub integer_literals: bool,
+}
+
+pub fn tokenize_with<'a>(
+    source: parser::Bite<'a>,
+    options: LexOptions,
+) -> impl Iterator<Item = Result<Token, LexError>> + 'a {
+    let mut tokens = tokenize_spanned_impl(source, options, &NO_ALIASES)
+        .map(|x| x.map(|(_, token)| token))
+        .peekable();
+    let mut last_token = None;
+    std::iter::from_fn(move || loop {
+        let next_token = tokens.next()?;
+        if let Ok(Token::Newline) = next_token {
+            let ends_statement = match &last_token {
+                Some(Token::CloseCurly) => true,
+                Some(token) => ends_operand(token),
+                None => false,
+            };
+            let continues_statement = match tokens.peek() {
+                Some(Ok(token)) => {
+                    matches!(
+                        token,
+                        Token::CloseParen | Token::CloseCurly | Token::CloseSquare | Token::Else
+                    ) || classify(token) == TokenClass::Operator
+                }
+                _ => true,
+            };
+            if !ends_statement || continues_statement {
+                continue;
+            }
+        }
+        last_token = next_token.as_ref().ok().cloned();
+        return Some(next_token);
+    })
+}
+
+// whether an operator may follow the token, so that a '-' after it must be a subtraction
+fn ends_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::LiteralNum(_)
+            | Token::LiteralInt(_)
+            | Token::LiteralStr(_)
+            | Token::Identifier(_)
+            | Token::Pi
+            | Token::E
+            | Token::Bang
+            | Token::Superscript(_)
+            | Token::CloseParen
+            | Token::CloseSquare
+    )
+}
+
+fn tokenize_spanned_impl<'a>(
+    source: parser::Bite<'a>,
+    options: LexOptions,
+    aliases: &'a AliasTable,
+) -> impl Iterator<Item = Result<(Range<usize>, Token), LexError>> + 'a {
     let mut bite = source;
     let mut done = false;
     let mut last_token = None;
+    let source_len = source.as_str().len();
+    let offset = move |bite: &parser::Bite| source_len - bite.as_str().len();
 
     let mut closure_stack = vec![];
     let mut closure_stack_iter = None;
 
     std::iter::from_fn(move || {
-        bite = bite.chomp(parser::Chomp::whitespace());
+        if options.newline_separators && !done {
+            bite = bite.chomp(parser::Chomp::whitespace_inline());
+            let start = offset(&bite);
+            if let Some(_) = bite.nibble(parser::Chomp::whitespace()) {
+                // a run of blank lines still only separates one pair of statements
+                last_token = Some(Token::Newline);
+                return Some(Ok((start..offset(&bite), Token::Newline)));
+            }
+        } else {
+            bite = bite.chomp(parser::Chomp::whitespace());
+        }
 
         let has_next = !bite.is_empty() && !done;
         if !has_next {
             // once token stream has ended append any missing open parens/brackets
+            let end = offset(&bite);
             return closure_stack_iter
-                .get_or_insert_with(|| closure_stack.clone().into_iter().map(|x| Ok(x)))
-                .next_back();
+                .get_or_insert_with(|| closure_stack.clone().into_iter())
+                .next_back()
+                .map(|x| Ok((end..end, x)));
         }
 
-        let next_token = tokenize_impl(&mut bite, last_token.as_ref());
+        let start = offset(&bite);
+        let next_token = match aliases.nibble(&mut bite) {
+            Some(token) => Ok(token),
+            None => tokenize_impl(&mut bite, last_token.as_ref(), options),
+        };
         if let Ok(next_token) = &next_token {
             last_token = Some(next_token.clone());
         }
         match &next_token {
             Ok(Token::OpenParen) => closure_stack.push(Token::CloseParen),
             Ok(Token::OpenCurly) => closure_stack.push(Token::CloseCurly),
+            Ok(Token::OpenSquare) => closure_stack.push(Token::CloseSquare),
             Ok(token) if closure_stack.last() == Some(token) => {
                 closure_stack.pop();
             }
@@ -75,43 +453,121 @@ pub fn tokenize<'a>(source: parser::Bite<'a>) -> impl Iterator<Item = Result<Tok
             _ => (),
         }
 
-        Some(next_token)
+        let next_token = next_token.map_err(|err| LexError {
+            offset: start,
+            ..err
+        });
+        Some(next_token.map(|token| (start..offset(&bite), token)))
     })
 }
 
-fn tokenize_impl(bite: &mut parser::Bite<'_>, last_token: Option<&Token>) -> Result<Token, String> {
+fn tokenize_impl(
+    bite: &mut parser::Bite<'_>,
+    last_token: Option<&Token>,
+    options: LexOptions,
+) -> Result<Token, LexError> {
     let token = if let Some(_) = bite.nibble(parser::Chomp::literal("sin")) {
         Token::Sine
-    } else if let Some(_) = bite.nibble(parser::Chomp::literal("log")) {
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("log2")) {
+        Token::Log2
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("log")) {
         Token::Log
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("cos")) {
         Token::Cosine
-    } else if let Some(_) = bite.nibble(parser::Chomp::literal("rand")) {
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("rand_normal")) {
+        Token::RandNormal
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("rand")) {
         Token::Rand
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("map")) {
+        Token::Map
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("simulate")) {
+        Token::Simulate
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("min")) {
+        Token::Min
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("max")) {
+        Token::Max
+    } else if let Some(_) = bite.nibble(parser::Chomp::call("beta")) {
+        Token::Beta
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("pow")) {
+        Token::Power
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("reduce")) {
+        Token::Reduce
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("lerp")) {
+        Token::Lerp
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("clamp01")) {
+        Token::Clamp01
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("round")) {
         Token::Round
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("floor")) {
         Token::Floor
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("csc")) {
+        Token::Csc
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("sec")) {
+        Token::Sec
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("cot")) {
+        Token::Cot
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("erfc")) {
+        Token::Erfc
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("erf")) {
+        Token::Erf
+    } else if let Some(_) = bite.nibble(parser::Chomp::call("gamma")) {
+        Token::Gamma
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("print")) {
+        Token::Print
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("let")) {
         Token::Let
+    } else if let Some(_) = bite.nibble(parser::Chomp::keyword("const")) {
+        Token::Const
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("if")) {
         Token::If
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("else")) {
         Token::Else
-    } else if let Some(_) = bite.nibble(parser::Chomp::literal("pi").or(parser::Chomp::char('𝜋')))
+    } else if let Some(_) =
+        bite.nibble(parser::Chomp::literal("pi").or(parser::Chomp::char_any(['𝜋', 'π'])))
     {
+        // 'π' has to be claimed here, before identifiers, which otherwise accept any greek letter
         Token::Pi
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("E")) {
         Token::E
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("sqrt")) {
         Token::Sqrt
+    } else if let Some(literal) = bite.nibble(parser::Chomp::string_literal()) {
+        Token::LiteralStr(literal.trim_matches('"').to_string())
+    } else if bite.can_nibble(parser::Chomp::char('"')) {
+        Err(lex_error(LexErrorKind::UnterminatedString, bite.as_str()))?
+    } else if let Some(digits) = bite.nibble(parser::Chomp::superscript()) {
+        let exponent = digits.chars().try_fold(0u32, |acc, c| {
+            let digit = parser::SUPERSCRIPT_DIGITS.iter().position(|x| *x == c)?;
+            acc.checked_mul(10)?.checked_add(digit as u32)
+        });
+        Token::Superscript(exponent.ok_or_else(|| lex_error(LexErrorKind::BadNumber, digits))?)
+    } else if let Some(_) = bite.nibble(parser::Chomp::literal("..=")) {
+        Token::DotDotEq
+    } else if let Some(_) = bite.nibble(parser::Chomp::literal("..")) {
+        Token::DotDot
     } else if bite.can_nibble(parser::Chomp::any_number())
-        && !matches!(last_token, Some(Token::LiteralNum(_)))
+        && !matches!(
+            last_token,
+            Some(Token::LiteralNum(_) | Token::LiteralInt(_))
+        )
+        // a sign only belongs to the number when nothing before it could be subtracted from
+        && !(bite.as_str().starts_with(['-', '−']) && last_token.map_or(false, ends_operand))
     {
         let literal = bite.nibble(parser::Chomp::any_number()).unwrap();
         // HACK: f64::from_str does not parse non-ascii char '−' (taken from google pixel's calc app)
         let replaced_literal = literal.replace('−', "-");
-        Token::LiteralNum(parse(&replaced_literal)?)
+        // integers too large for an i64 are still worth keeping as an (inexact) float
+        let int_value = match options.integer_literals && !literal.contains('.') {
+            true => replaced_literal.parse().ok(),
+            false => None,
+        };
+        match int_value {
+            Some(value) => Token::LiteralInt(value),
+            None => {
+                let value = replaced_literal.parse();
+                Token::LiteralNum(value.map_err(|_| lex_error(LexErrorKind::BadNumber, literal))?)
+            }
+        }
     } else if let Some(_) = bite.nibble(parser::Chomp::char('(')) {
         Token::OpenParen
     } else if let Some(_) = bite.nibble(parser::Chomp::char(')')) {
@@ -120,6 +576,10 @@ fn tokenize_impl(bite: &mut parser::Bite<'_>, last_token: Option<&Token>) -> Res
         Token::OpenCurly
     } else if let Some(_) = bite.nibble(parser::Chomp::char('}')) {
         Token::CloseCurly
+    } else if let Some(_) = bite.nibble(parser::Chomp::char('[')) {
+        Token::OpenSquare
+    } else if let Some(_) = bite.nibble(parser::Chomp::char(']')) {
+        Token::CloseSquare
     } else if let Some(_) =
         bite.nibble(parser::Chomp::literal("=>").or(parser::Chomp::char_any(['⇒', '➪'])))
     {
@@ -131,9 +591,14 @@ fn tokenize_impl(bite: &mut parser::Bite<'_>, last_token: Option<&Token>) -> Res
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("==")) {
         Token::Eq
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("!=")) {
+        // must stay ahead of the bare '!' so that '3!=2' still compares rather than factorials
         Token::NotEq
+    } else if let Some(_) = bite.nibble(parser::Chomp::char('!')) {
+        Token::Bang
     } else if let Some(_) = bite.nibble(parser::Chomp::char('=')) {
         Token::Equals
+    } else if let Some(_) = bite.nibble(parser::Chomp::literal(":=")) {
+        Token::ColonEquals
     } else if let Some(_) = bite.nibble(parser::Chomp::literal("<=").or(parser::Chomp::char('≤')))
     {
         Token::LessThanEquals
@@ -157,23 +622,23 @@ fn tokenize_impl(bite: &mut parser::Bite<'_>, last_token: Option<&Token>) -> Res
     } else if let Some(_) = bite.nibble(parser::Chomp::char('%').or(parser::Chomp::literal("mod")))
     {
         Token::Mod
-    } else if let Some(indent) = bite.nibble(parser::Chomp::alphanumeric()) {
+    } else if let Some(indent) = bite.nibble(parser::Chomp::alphanumeric_extended()) {
         Token::Identifier(indent.to_string())
     } else if let Some(indent) = bite.nibble(parser::Chomp::char_any(['𝒂', '𝒃', '𝒙', '𝒚']))
     {
         Token::Identifier(indent.to_string())
     } else {
-        Err(format!("Could not parse: {}", bite.as_str()))?
+        Err(lex_error(LexErrorKind::UnknownChar, bite.as_str()))?
     };
 
     Ok(token)
 }
 
-fn parse<T: FromStr>(literal: &str) -> Result<T, String>
-where
-    <T as FromStr>::Err: Display,
-{
-    literal
-        .parse()
-        .map_err(|e| format!("Could not parse '{literal}': {e}"))
+// the offset is filled in by the caller, which knows where the failed token started
+fn lex_error(kind: LexErrorKind, snippet: &str) -> LexError {
+    LexError {
+        offset: 0,
+        kind,
+        snippet: snippet.to_string(),
+    }
 }
@@ -1,67 +1,246 @@
+use std::ops::Range;
+
 use compiler::Compiler;
 use vm::VM;
 
 pub mod compiler;
+pub mod decompiler;
 pub mod lexer;
+pub mod optimizer;
+pub mod palette;
 pub mod parser;
+pub mod prelude;
 pub mod pretty;
+pub mod resolver;
 pub mod vm;
 
+/// The error returned when a program fails to tokenize, compile or run; a human-readable message.
+pub type CalcError = String;
+
 pub fn compute(vm: &mut VM, input: &str) -> Option<f64> {
-    let program = match compile(input) {
+    let program = match compile_for(vm, input) {
         Ok(value) => value,
         Err(msg) => {
             eprintln!("{}", msg);
             return None;
         }
     };
+    if program.is_empty() {
+        return None;
+    }
 
-    match vm.run(&program) {
-        Ok(_) => {}
-        Err(err) => {
-            eprintln!("ERROR: could not compute expression: {err}");
-            return None;
+    let result = vm.run(&program);
+    for warning in vm.take_warnings() {
+        eprintln!("WARN: {warning}");
+    }
+    if let Err(err) = result {
+        eprintln!("ERROR: could not compute expression: {err}");
+        vm.clear_stack();
+        return None;
+    }
+
+    let result = vm.pop_result();
+    // anything left below the result is residue from earlier statements
+    vm.clear_stack();
+    result
+}
+
+/// Compiles and runs `input` on a fresh VM, for one-off calculations that don't need any state
+/// kept between them.
+pub fn eval(input: &str) -> Result<Option<f64>, CalcError> {
+    let program = compile(input)?;
+    let mut vm = VM::new();
+    vm.run(&program)
+        .map_err(|err| format!("ERROR: could not compute expression: {err}"))?;
+    Ok(vm.pop_result())
+}
+
+/// Lists the functions (with their arities) and constants every program can use without defining
+/// them, such as 'sin' or 'pi', for editor autocompletion.
+pub fn builtins() -> compiler::Builtins {
+    let mut builtins = compiler::keyword_builtins();
+    builtins.functions.extend(vm::default_natives());
+    builtins
+}
+
+/// Evaluates `program` at `steps` evenly spaced values of `var` from `start` to `end` inclusive.
+/// Samples that fail to evaluate come back as NaN so a grapher can leave a gap.
+pub fn eval_samples(
+    vm: &mut VM,
+    program: &[vm::Instruction],
+    var: &str,
+    start: f64,
+    end: f64,
+    steps: usize,
+) -> Vec<f64> {
+    let step = if steps > 1 {
+        (end - start) / (steps - 1) as f64
+    } else {
+        0.0
+    };
+    (0..steps)
+        .map(|i| {
+            let x = start + step * i as f64;
+            match vm.run_with_bindings(program, &[(var, x)]) {
+                Ok(_) => vm.pop_result().unwrap_or(f64::NAN),
+                Err(_) => f64::NAN,
+            }
+        })
+        .collect()
+}
+
+/// Runs each non-blank line of `script` in turn, stopping at the first line that fails to compile
+/// or run. A line that leaves a bracket open continues onto the next ones until it is closed
+/// (example: a function body in braces), with errors reported at the line it began on. Returns
+/// the value left by the last line, if any.
+pub fn run_script(vm: &mut VM, script: &str) -> Result<Option<f64>, CalcError> {
+    let mut run_chunk = |chunk: &str, line_no: usize| {
+        let program = compile_for(vm, chunk).map_err(|err| format!("line {line_no}: {err}"))?;
+        vm.run(&program)
+            .map_err(|err| format!("line {line_no}: {err}"))?;
+        let result = vm.pop_result();
+        vm.clear_stack();
+        Ok::<_, CalcError>(result)
+    };
+    let mut result = None;
+    let mut chunk = String::new();
+    let mut chunk_line_no = 0;
+    for (idx, line) in script.lines().enumerate() {
+        if chunk.is_empty() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            chunk_line_no = idx + 1;
+        }
+        chunk.push_str(line);
+        chunk.push('\n');
+        // the lexer auto-closes open brackets with tokens that have no source to span
+        let is_open = lexer::tokenize_spanned(parser::Bite::new(&chunk))
+            .any(|token| matches!(token, Ok((span, _)) if span.is_empty()));
+        if !is_open {
+            result = run_chunk(&chunk, chunk_line_no)?;
+            chunk.clear();
         }
     }
+    if !chunk.is_empty() {
+        result = run_chunk(&chunk, chunk_line_no)?;
+    }
+    Ok(result)
+}
 
-    vm.pop_result()
+pub fn compile(input: &str) -> Result<Vec<vm::Instruction>, CalcError> {
+    compile_with(input, Default::default())
 }
 
-pub fn compile(input: &str) -> Result<Vec<vm::Instruction>, String> {
-    let tokens = tokenize(input)?;
+/// Compiles `input` like `compile`, adding any compile warnings (example: a nested 'let' that
+/// reassigns an outer variable) to `vm`'s warnings, to be reported with those from running it.
+pub fn compile_for(vm: &mut VM, input: &str) -> Result<Vec<vm::Instruction>, CalcError> {
+    let (program, warnings) = compile_impl(input, Default::default())?;
+    warnings.into_iter().for_each(|warning| vm.warn(warning));
+    Ok(program)
+}
+
+pub fn compile_with(
+    input: &str,
+    options: compiler::CompileOptions,
+) -> Result<Vec<vm::Instruction>, CalcError> {
+    compile_impl(input, options).map(|(program, _)| program)
+}
+
+fn compile_impl(
+    input: &str,
+    options: compiler::CompileOptions,
+) -> Result<(Vec<vm::Instruction>, Vec<String>), CalcError> {
+    let lex_options = lexer::LexOptions {
+        newline_separators: options.newline_separators,
+        integer_literals: options.integer_literals,
+    };
+    let tokens = tokenize_with(input, lex_options)?;
+    if tokens.is_empty() {
+        return Ok((vec![], vec![]));
+    }
     let mut compiler = Compiler::new(&tokens);
+    compiler.set_implicit_multiplication(options.implicit_multiplication);
+    compiler.set_optimize(options.optimize);
     let program = match compiler.compile() {
         Ok(x) => x,
         Err(err) => {
             return Err(format!("ERROR: could not compile program: {err}"));
         }
     };
-    Ok(program)
+    Ok((program, compiler.take_warnings()))
 }
 
-pub fn format(input: &str) -> Result<String, String> {
+pub fn format(input: &str) -> Result<String, CalcError> {
     format_impl(input, pretty::PrettyFormat::Spaced)
 }
 
-pub fn format_pretty(input: &str) -> Result<String, String> {
+pub fn format_pretty(input: &str) -> Result<String, CalcError> {
     format_impl(input, pretty::PrettyFormat::Indented)
 }
 
-pub fn minify(input: &str) -> Result<String, String> {
+pub fn minify(input: &str) -> Result<String, CalcError> {
     format_impl(input, pretty::PrettyFormat::Minified)
 }
 
-fn format_impl(input: &str, which: pretty::PrettyFormat) -> Result<String, String> {
+/// Reduces `input` to a key shared by every spacing of the same expression (example: '1+2' and
+/// '1  +  2'), for deduplicating stored expressions while displaying them as typed. This is the
+/// `minify` form, so redundant parentheses are dropped too ('(1 + 2)' shares the key of '1+2'),
+/// while those that change the grouping are kept ('8-(4-2)' and '8-4-2' differ).
+pub fn canonical(input: &str) -> Result<String, CalcError> {
+    minify(input)
+}
+
+pub fn format_with(
+    input: &str,
+    which: pretty::PrettyFormat,
+    options: pretty::PrettyOptions,
+) -> Result<String, CalcError> {
     let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Ok(String::new());
+    }
     let mut compiler = Compiler::new(&tokens);
+    compiler.set_preserve_groups(options.preserve_groups);
     let expression_tree = compiler.compile_expression_tree()?;
-    let formatted = pretty::pretty_print(expression_tree, which);
+    let formatted = pretty::pretty_print(expression_tree, which, options);
     Ok(formatted)
 }
 
+pub fn parse(input: &str) -> Result<compiler::RecursiveExpression, CalcError> {
+    let tokens = tokenize(input)?;
+    let mut compiler = Compiler::new(&tokens);
+    compiler.compile_expression_tree()
+}
+
+fn format_impl(input: &str, which: pretty::PrettyFormat) -> Result<String, String> {
+    format_with(input, which, Default::default())
+}
+
+pub fn token_iter(input: &str) -> impl Iterator<Item = Result<lexer::Token, lexer::LexError>> + '_ {
+    // leading whitespace is skipped by the lexer, so error offsets stay relative to 'input'
+    lexer::tokenize(parser::Bite::new(input))
+}
+
+pub fn highlight(input: &str) -> Vec<(Range<usize>, lexer::TokenClass)> {
+    // auto-closed brackets don't appear in the source so have nothing to highlight
+    lexer::tokenize_spanned(parser::Bite::new(input))
+        .map_while(Result::ok)
+        .filter(|(span, _)| !span.is_empty())
+        .map(|(span, token)| (span, lexer::classify(&token)))
+        .collect()
+}
+
 fn tokenize(input: &str) -> Result<Vec<lexer::Token>, String> {
-    let source = parser::Bite::new(&input).chomp(parser::Chomp::whitespace());
-    let tokens = lexer::tokenize(source).collect();
+    let tokens = token_iter(input).collect();
+    match tokens {
+        Ok(x) => Ok(x),
+        Err(err) => Err(format!("ERROR: could not interpret input tokens: {err}")),
+    }
+}
+
+fn tokenize_with(input: &str, options: lexer::LexOptions) -> Result<Vec<lexer::Token>, String> {
+    let tokens = lexer::tokenize_with(parser::Bite::new(input), options).collect();
     match tokens {
         Ok(x) => Ok(x),
         Err(err) => Err(format!("ERROR: could not interpret input tokens: {err}")),
@@ -114,6 +293,38 @@ mod tests {
         assert_eq!(None, tokens.next());
     }
 
+    #[test]
+    fn can_parse_bang_variants() {
+        let tokens: Result<Vec<_>, _> = lexer::tokenize("3 != 2".into()).collect();
+        let expected = vec![Token::LiteralNum(3.0), Token::NotEq, Token::LiteralNum(2.0)];
+        assert_eq!(Ok(expected.clone()), tokens);
+
+        let tokens: Result<Vec<_>, _> = lexer::tokenize("3!=2".into()).collect();
+        assert_eq!(Ok(expected), tokens);
+
+        let tokens: Result<Vec<_>, _> = lexer::tokenize("!0".into()).collect();
+        assert_eq!(Ok(vec![Token::Bang, Token::LiteralNum(0.0)]), tokens);
+
+        let tokens: Result<Vec<_>, _> = lexer::tokenize("3!".into()).collect();
+        assert_eq!(Ok(vec![Token::LiteralNum(3.0), Token::Bang]), tokens);
+    }
+
+    #[test]
+    fn can_compute_not_and_factorial() {
+        let mut vm = VM::new();
+        assert_eq!(1.0, compute(&mut vm, "3 != 2").unwrap());
+        assert_eq!(1.0, compute(&mut vm, "!0").unwrap());
+        assert_eq!(0.0, compute(&mut vm, "!5").unwrap());
+        assert_eq!(1.0, compute(&mut vm, "!(1 == 2)").unwrap());
+        assert_eq!(6.0, compute(&mut vm, "3!").unwrap());
+        assert_eq!(1.0, compute(&mut vm, "0!").unwrap());
+        assert_eq!(26.0, compute(&mut vm, "2 + 4!").unwrap());
+        assert_eq!(720.0, compute(&mut vm, "(1 + 2)!!").unwrap());
+        assert!(compute(&mut vm, "2.5!").unwrap().is_nan());
+
+        assert_eq!("(1 + 2)! + !x", super::format("(1+2)! + !x").unwrap());
+    }
+
     #[test]
     fn can_compile_define_fn() {
         let mut instructions = instr_iter("let s = (x) => sin(x) + x").into_iter();
@@ -184,6 +395,85 @@ mod tests {
         assert_eq!(2.0, compute(&mut vm, "s2(20, 9, 3)").unwrap().round());
     }
 
+    #[test]
+    fn can_pass_args_in_order() {
+        let mut instructions = instr_iter("f(1, 2, 3)").into_iter();
+        assert_eq!(Some(Instruction::Push(1.0)), instructions.next());
+        assert_eq!(Some(Instruction::Push(2.0)), instructions.next());
+        assert_eq!(Some(Instruction::Push(3.0)), instructions.next());
+
+        let mut vm = VM::new();
+        compute(&mut vm, "let s3 = (a, b, c, d) => ((a - b) / c)^d");
+        assert_eq!(Some(4.0), compute(&mut vm, "s3(10, 2, 4, 2)"));
+        assert_eq!(
+            Some(9.0),
+            compute(&mut vm, "[(a, b, c) => a * b - c][0](2, 5, 1)")
+        );
+        assert_eq!(
+            Some(-7.0),
+            compute(&mut vm, "reduce([1, 2, 3], (acc, x) => acc - x, -1)")
+        );
+        let handle = vm.resolve_function("s3").unwrap();
+        assert_eq!(
+            Ok(Some(8.0)),
+            vm.call_resolved(&handle, &[7.0, 1.0, 3.0, 3.0])
+        );
+
+        compute(
+            &mut vm,
+            "let count = (i, n, acc) => if (i < n) { count(i + 1, n, acc - i) } else { acc }",
+        );
+        assert_eq!(Some(-10.0), compute(&mut vm, "count(0, 5, 0)"));
+
+        vm.register_native("sub3", 3, |args| Ok(args[0] - args[1] - args[2]));
+        assert_eq!(Some(5.0), compute(&mut vm, "sub3(10, 3, 2)"));
+
+        let info = vm.get_function("s3").unwrap();
+        assert_eq!(vec!["a", "b", "c", "d"], info.params);
+    }
+
+    #[test]
+    fn can_pool_constants() {
+        let program = vm::pool_constants(&compile("2*2 + 2*2").unwrap());
+        assert_eq!(vec![2.0], program.constants);
+        let pushes = program.instructions.iter();
+        let pushes = pushes.filter(|x| **x == Instruction::PushConst(0)).count();
+        assert_eq!(4, pushes);
+        assert!(!program.instructions.contains(&Instruction::Push(2.0)));
+
+        let mut vm = VM::new();
+        assert_eq!(Ok(()), vm.run_pooled(&program));
+        assert_eq!(Some(8.0), vm.pop_result());
+
+        // function bodies keep their literals, so they still run once the pool is gone
+        let program = compile("let f = (x) => x + 0.5; if (1) { 0.5 } else { 3 }").unwrap();
+        let program = vm::pool_constants(&program);
+        assert_eq!(vec![1.0, 0.5, 3.0], program.constants);
+        assert_eq!(Ok(()), vm.run_pooled(&program));
+        assert_eq!(Some(0.5), vm.pop_result());
+        assert_eq!(Some(2.5), compute(&mut vm, "f(2)"));
+        assert!(vm.run(&[Instruction::PushConst(0)]).is_err());
+    }
+
+    #[test]
+    fn can_report_program_stats() {
+        let program =
+            compile("let loop = (i, n, f) => { if (i < n) { f(); loop(i + 1, n, f); } else {} }")
+                .unwrap();
+        let stats = vm::program_stats(&program);
+
+        assert_eq!(25, stats.instruction_count);
+        assert_eq!(2, stats.max_depth);
+        assert!(stats.calls_routines);
+        assert!(!stats.uses_random);
+
+        let stats = vm::program_stats(&compile("2 * rand()").unwrap());
+        assert_eq!(3, stats.instruction_count);
+        assert_eq!(0, stats.max_depth);
+        assert!(!stats.calls_routines);
+        assert!(stats.uses_random);
+    }
+
     #[test]
     fn can_compile_sin() {
         let mut instructions = instr_iter("sin(90)").into_iter();
@@ -376,156 +666,2271 @@ mod tests {
     }
 
     #[test]
-    fn can_compute_sqrt() {
+    fn can_assign_captured_variables_from_closures() {
         let mut vm = VM::new();
-        assert_eq!(10.0, compute(&mut vm, "sqrt(100)").unwrap().round());
+        compute(
+            &mut vm,
+            "let loop = (i, n, f) => { if (i < n) { f(); loop(i + 1, n, f); } else {} }",
+        );
+
+        // the closure updates the local of the function that is running it, two frames up
+        let outer = "let outer = () => { let c = 0; loop(0, 3, () => let c = c + 1); c }";
+        compute(&mut vm, outer);
+        assert_eq!(3.0, compute(&mut vm, "outer()").unwrap());
+
+        // nested closures all resolve to the same nearest binding
+        let nested = "let nested = () => { let c = 10; loop(0, 2, () => loop(0, 2, () => let c = c + 1)); c }";
+        compute(&mut vm, nested);
+        assert_eq!(14.0, compute(&mut vm, "nested()").unwrap());
+
+        // a parameter of the same name is nearer than the outer binding, so that stays untouched
+        compute(&mut vm, "let c = 5; let bump = (c) => let c = c + 1");
+        compute(&mut vm, "bump(1)");
+        assert_eq!(5.0, compute(&mut vm, "c").unwrap());
+
+        // a name with no binding yet is created in the closure's own frame and dropped with it
+        compute(&mut vm, "let fresh = () => let z = 1; fresh()");
+        vm.set_missing_default(vm::MissingPolicy::Error);
+        assert!(vm.run(&compile("z").unwrap()).is_err());
     }
 
     #[test]
-    fn can_compile_multiple() {
-        let mut instructions = instr_iter("3 - sin(90)").into_iter();
+    fn can_compile_tail_calls() {
+        let program = compile("let count = (i) => if (i > 0) { count(i - 1) }").unwrap();
+        let Some(Instruction::PushRoutine(routine)) = program.first() else {
+            panic!("expected routine, found {program:?}");
+        };
+        let Some(Instruction::SkipIfNot(block)) = routine.last() else {
+            panic!("expected conditional, found {routine:?}");
+        };
+        assert_eq!(
+            [
+                Instruction::TailCallRoutine(1),
+                Instruction::Discard,
+                Instruction::Leave
+            ],
+            block[block.len() - 3..]
+        );
 
-        assert_eq!(Some(Instruction::Push(3.0)), instructions.next());
-        assert_eq!(Some(Instruction::Push(90.0)), instructions.next());
-        assert_eq!(Some(Instruction::Sine), instructions.next());
-        assert_eq!(Some(Instruction::Sub), instructions.next());
-        assert_eq!(None, instructions.next());
+        let program = compile("let count = (i) => count(i - 1) + 1").unwrap();
+        assert!(!format!("{program:?}").contains("TailCallRoutine"));
     }
 
     #[test]
-    fn can_compute_multiple() {
+    fn can_compute_tail_recursive_loop() {
         let mut vm = VM::new();
-        assert_eq!(2.0, compute(&mut vm, "3 - sin(90)").unwrap().round());
+        compute(
+            &mut vm,
+            "let count = (i, n) => { if (i < n) { count(i + 1, n) } else { i } }",
+        );
+        assert_eq!(100_000.0, compute(&mut vm, "count(0, 100000)").unwrap());
+
+        compute(&mut vm, "let y = 0");
+        compute(
+            &mut vm,
+            "let loop = (i, n, f) => { if (i < n) { f(); loop(i + 1, n, f); } else {} }",
+        );
+        compute(&mut vm, "loop(0, 100000, () => let y = y + 1)");
+        assert_eq!(100_000.0, compute(&mut vm, "y").unwrap());
     }
 
     #[test]
-    fn can_compile_with_basic_parens() {
-        let mut instructions = instr_iter("2 + (20 - 10)").into_iter();
+    fn can_trace_errors_through_calls() {
+        let mut vm = VM::new();
+        vm.set_strict_math(true);
+        compute(&mut vm, "let g = () => 1 / 0; let f = () => g()");
+        let err = vm.run(&compile("f()").unwrap()).unwrap_err();
+        assert_eq!("in f -> g: division by zero", err);
 
-        assert_eq!(Some(Instruction::Push(2.0)), instructions.next());
-        assert_eq!(Some(Instruction::Push(20.0)), instructions.next());
-        assert_eq!(Some(Instruction::Push(10.0)), instructions.next());
-        assert_eq!(Some(Instruction::Sub), instructions.next());
-        assert_eq!(Some(Instruction::Add), instructions.next());
-        assert_eq!(None, instructions.next());
+        let err = vm.run(&compile("1 / 0").unwrap()).unwrap_err();
+        assert_eq!("division by zero", err);
+
+        vm.set_stack_trace(false);
+        let err = vm.run(&compile("f()").unwrap()).unwrap_err();
+        assert_eq!("division by zero", err);
     }
 
     #[test]
-    fn can_compute_with_basic_parens() {
+    fn can_run_stack_instructions() {
         let mut vm = VM::new();
-        assert_eq!(20.0, compute(&mut vm, "2 * (20 - 10)").unwrap().round());
-        assert_eq!(30.0, compute(&mut vm, "(2 * 20) - 10").unwrap().round());
+        let program = [Instruction::Push(3.0), Instruction::Dup, Instruction::Add];
+        vm.run(&program).unwrap();
+        assert_eq!(Some(6.0), vm.pop_result());
+
+        let program = [
+            Instruction::Push(3.0),
+            Instruction::Push(4.0),
+            Instruction::Pop,
+        ];
+        vm.run(&program).unwrap();
+        assert_eq!(Some(3.0), vm.pop_result());
+
+        let program = [
+            Instruction::Push(10.0),
+            Instruction::Push(3.0),
+            Instruction::Swap,
+            Instruction::Sub,
+        ];
+        vm.run(&program).unwrap();
+        assert_eq!(Some(-7.0), vm.pop_result());
+
+        assert!(vm.run(&[Instruction::Dup]).is_err());
+        assert!(vm.run(&[Instruction::Pop]).is_err());
+        assert!(vm
+            .run(&[Instruction::Push(1.0), Instruction::Swap])
+            .is_err());
     }
 
     #[test]
-    fn can_compute_with_implicit_multiplication() {
+    fn can_compute_array_arithmetic() {
         let mut vm = VM::new();
-        assert_eq!(20.0, compute(&mut vm, "2(20 - 10)").unwrap().round());
-        assert_eq!(None, compute(&mut vm, "let x = 3"));
-        assert_eq!(16.0, compute(&mut vm, "(x)(x) + 2x + 1").unwrap().round());
+        vm.run(&compile("[1, 2, 3] + [4, 5, 6]").unwrap()).unwrap();
+        assert_eq!(Some(vec![5.0, 7.0, 9.0]), vm.peek_array());
+
+        vm.run(&compile("2 * [1, 2, 3]").unwrap()).unwrap();
+        assert_eq!(Some(vec![2.0, 4.0, 6.0]), vm.peek_array());
+
+        vm.run(&compile("[1, 2, 3] - 1").unwrap()).unwrap();
+        assert_eq!(Some(vec![0.0, 1.0, 2.0]), vm.peek_array());
+
+        vm.run(&compile("let a = [1, 2]; a^2 + a").unwrap())
+            .unwrap();
+        assert_eq!(Some(vec![2.0, 6.0]), vm.peek_array());
+
+        vm.run(&compile("[]").unwrap()).unwrap();
+        assert_eq!(Some(vec![]), vm.peek_array());
+
+        let err = vm.run(&compile("[1, 2] + [1, 2, 3]").unwrap()).unwrap_err();
+        assert_eq!("array length mismatch: 2 vs 3", err);
+
+        assert_eq!("[1, 2, 3] * 2", super::format("[1,2,3]*2").unwrap());
+        assert_eq!("[1,2,3]*2", super::minify("[ 1, 2, 3 ] * 2").unwrap());
     }
 
     #[test]
-    fn can_compute_with_precedence() {
+    fn can_compute_map_reduce() {
         let mut vm = VM::new();
-        assert_eq!(11.0, compute(&mut vm, "2 * 3 + 5").unwrap().round());
-        assert_eq!(17.0, compute(&mut vm, "2 + 3 * 5").unwrap().round());
+        compute(&mut vm, "let f = (x) => x * 2");
+        vm.run(&compile("map([1, 2, 3], f)").unwrap()).unwrap();
+        assert_eq!(Some(vec![2.0, 4.0, 6.0]), vm.peek_array());
+
+        assert_eq!(
+            12.0,
+            compute(&mut vm, "reduce(map([1, 2, 3], f), (a, b) => a + b, 0)").unwrap()
+        );
+        assert_eq!(
+            123.0,
+            compute(&mut vm, "reduce([1, 2, 3], (a, b) => a * 10 + b, 0)").unwrap()
+        );
+        assert_eq!(
+            5.0,
+            compute(&mut vm, "reduce([], (a, b) => a + b, 5)").unwrap()
+        );
+
+        assert!(vm.run(&compile("map(1, f)").unwrap()).is_err());
+        assert!(vm.run(&compile("map([1], 2)").unwrap()).is_err());
+        assert!(vm.run(&compile("map([1], (x) => {})").unwrap()).is_err());
+
+        assert_eq!(
+            "reduce(map(a, f), (a, b) => a + b, 0)",
+            super::format("reduce(map(a,f),(a,b)=>a+b,0)").unwrap()
+        );
+
+        // names merely starting with 'map' or 'reduce' are still identifiers
+        assert_eq!(Some(1.0), compute(&mut vm, "let mapping = 1; mapping"));
+        assert_eq!(Some(2.0), compute(&mut vm, "let reducer = 2; reducer"));
     }
 
     #[test]
-    fn can_compute_variables() {
+    fn can_compute_ranges() {
         let mut vm = VM::new();
-        compute(&mut vm, "let x = 1 + 2");
-        assert_eq!(5.0, compute(&mut vm, "x + 2").unwrap().round());
+        vm.run(&compile("1..4").unwrap()).unwrap();
+        assert_eq!(Some(vec![1.0, 2.0, 3.0]), vm.peek_array());
+        vm.run(&compile("1..=3").unwrap()).unwrap();
+        assert_eq!(Some(vec![1.0, 2.0, 3.0]), vm.peek_array());
+        vm.run(&compile("let n = 2; 0..n+1").unwrap()).unwrap();
+        assert_eq!(Some(vec![0.0, 1.0, 2.0]), vm.peek_array());
+        vm.run(&compile("3..1").unwrap()).unwrap();
+        assert_eq!(Some(vec![]), vm.peek_array());
+        vm.run(&compile("1.5..3").unwrap()).unwrap();
+        assert_eq!(Some(vec![1.5, 2.5]), vm.peek_array());
+
+        assert_eq!(
+            6.0,
+            compute(&mut vm, "reduce(1..=3, (a, b) => a + b, 0)").unwrap()
+        );
+        assert!(vm.run(&compile("0..10^9").unwrap()).is_err());
+        assert_eq!("(1..n) * 2", super::format("(1..n)*2").unwrap());
+        assert_eq!("1..=n + 1", super::format("1..=n+1").unwrap());
     }
 
     #[test]
-    fn can_compute_blocks() {
+    fn can_concat_strings() {
         let mut vm = VM::new();
-        compute(&mut vm, "let x = { let y = 1 + 2; y + 3 }");
-        assert_eq!(6.0, compute(&mut vm, "x").unwrap().round());
+        vm.run(&compile("\"foo\" + \"bar\"").unwrap()).unwrap();
+        assert_eq!(Some("foobar"), vm.peek_str());
+        vm.run(&compile("\"n=\" + 5").unwrap()).unwrap();
+        assert_eq!(Some("n=5"), vm.peek_str());
+        vm.run(&compile("let x = 0.5; \"x = \" + x + \"!\"").unwrap())
+            .unwrap();
+        assert_eq!(Some("x = 0.5!"), vm.peek_str());
+        vm.run(&compile("1 + 2 + \" apples\"").unwrap()).unwrap();
+        assert_eq!(Some("3 apples"), vm.peek_str());
+
+        assert!(vm.run(&compile("\"a\" + [1]").unwrap()).is_err());
+        assert!(compile("\"foo").is_err());
+        assert_eq!("\"a b\" + 1", super::format("\"a b\"+1").unwrap());
     }
 
     #[test]
-    fn can_compute_block_fn() {
+    fn can_collect_warnings() {
         let mut vm = VM::new();
-        compute(
-            &mut vm,
-            "let calc = (x) => { let y = 2(x + 1); y^2 + y + 3 }",
-        );
-        assert_eq!(45.0, compute(&mut vm, "calc(2)").unwrap().round());
+        assert_eq!(1.0, compute(&mut vm, "missing + 1").unwrap());
+        assert_eq!(vec!["missing variable 'missing'"], vm.take_warnings());
+        assert!(vm.take_warnings().is_empty());
 
-        compute(&mut vm, "let y = 15");
-        compute(&mut vm, "let calc2 = ( x ) => { let y = y * x }");
-        assert_eq!(None, compute(&mut vm, "calc2(3)"));
-        assert_eq!(45.0, compute(&mut vm, "y").unwrap().round());
+        compute(&mut vm, "let x = 2; x(1)");
+        assert_eq!(1, vm.take_warnings().len());
+    }
 
-        compute(&mut vm, "let calc = (x, y) => { x^2 + y^2 }");
-        assert_eq!(13.0, compute(&mut vm, "calc(2, 3)").unwrap().round());
+    #[test]
+    fn can_iterate_tokens_lazily() {
+        let mut tokens = token_iter("sin(90)+1");
+        assert_eq!(Some(Ok(Token::Sine)), tokens.next());
+        assert_eq!(Some(Ok(Token::OpenParen)), tokens.next());
+        assert_eq!(Some(Ok(Token::LiteralNum(90.0))), tokens.next());
+        assert_eq!(Some(Ok(Token::CloseParen)), tokens.next());
+        assert_eq!(Some(Ok(Token::Plus)), tokens.next());
+        assert_eq!(Some(Ok(Token::LiteralNum(1.0))), tokens.next());
+        assert_eq!(None, tokens.next());
 
-        compute(&mut vm, "let calc = () => {}");
-        compute(&mut vm, "calc()");
-        assert!(vm.pop_result().is_none());
+        // missing closing parens are still appended once the input runs out
+        let tokens: Result<Vec<_>, _> = token_iter("  sin(90").collect();
+        let expected = vec![
+            Token::Sine,
+            Token::OpenParen,
+            Token::LiteralNum(90.0),
+            Token::CloseParen,
+        ];
+        assert_eq!(Ok(expected), tokens);
+    }
 
-        compute(
-            &mut vm,
-            r#"
-            let repeat = (x, n) => {
-                let c = floor(log(x)) + 1;
-                let y = 0;
-                let loop = (i, fn) => {
-                    if (i > 0) {
-                        fn(i - 1);
-                        loop(i - 1, fn)
-                    }
-                };
-                loop(n, (i) => {
-                    let y = y + (x * 10^(i * c))
-                });
-                y
-            }"#,
+    #[test]
+    fn can_report_lex_errors() {
+        use crate::lexer::{LexError, LexErrorKind};
+
+        let err = token_iter("1 + $2").find_map(Result::err).unwrap();
+        let expected = LexError {
+            offset: 4,
+            kind: LexErrorKind::UnknownChar,
+            snippet: "$2".to_string(),
+        };
+        assert_eq!(expected, err);
+        assert_eq!("Could not parse: $2", err.to_string());
+
+        let err = token_iter("  --1").find_map(Result::err).unwrap();
+        assert_eq!(LexErrorKind::BadNumber, err.kind);
+        assert_eq!(2, err.offset);
+        assert_eq!(
+            "Could not parse '-': invalid float literal",
+            err.to_string()
         );
-        assert_eq!(98989898.0, compute(&mut vm, "repeat(98, 4)").unwrap().round());
+
+        let err = token_iter("\"abc").find_map(Result::err).unwrap();
+        assert_eq!(LexErrorKind::UnterminatedString, err.kind);
+        assert_eq!("Unterminated string literal: \"abc", err.to_string());
+
+        let err = compile("1 + $2").unwrap_err();
+        assert!(err.ends_with("Could not parse: $2"), "{err}");
     }
 
     #[test]
-    fn can_pretty_print() {
-        let formatted = super::format("let calc= ( x, ) =>sin (90)").unwrap();
-        assert_eq!("let calc = (x) => sin(90)", formatted);
+    fn can_highlight_tokens() {
+        use crate::lexer::TokenClass;
+
+        let input = "let x = sin(90";
+        let classes = highlight(input);
+        let spans: Vec<_> = classes
+            .iter()
+            .map(|(span, class)| (&input[span.clone()], *class))
+            .collect();
+        let expected = vec![
+            ("let", TokenClass::Keyword),
+            ("x", TokenClass::Identifier),
+            ("=", TokenClass::Operator),
+            ("sin", TokenClass::Function),
+            ("(", TokenClass::Paren),
+            ("90", TokenClass::Number),
+        ];
+        assert_eq!(expected, spans);
+
+        let classes = highlight(" \"a\" + 1 $");
+        assert_eq!(
+            vec![1..4, 5..6, 7..8],
+            classes.into_iter().map(|x| x.0).collect::<Vec<_>>()
+        );
+    }
 
-        let formatted = super::format("example(1-1)").unwrap();
-        assert_eq!("example(1 - 1)", formatted);
+    #[test]
+    fn can_tokenize_with_single_lexer() {
+        let tokens: Result<Vec<_>, _> = token_iter("cos(x) + sin(y) <= { 1 }").collect();
+        let expected = vec![
+            Token::Cosine,
+            Token::OpenParen,
+            Token::Identifier(String::from("x")),
+            Token::CloseParen,
+            Token::Plus,
+            Token::Sine,
+            Token::OpenParen,
+            Token::Identifier(String::from("y")),
+            Token::CloseParen,
+            Token::LessThanEquals,
+            Token::OpenCurly,
+            Token::LiteralNum(1.0),
+            Token::CloseCurly,
+        ];
+        assert_eq!(Ok(expected.clone()), tokens);
+
+        // the spanned tokenizer shares the same implementation
+        let source = parser::Bite::new("cos(x) + sin(y) <= { 1 }");
+        let spanned: Result<Vec<_>, _> = lexer::tokenize_spanned(source)
+            .map(|x| x.map(|(_, token)| token))
+            .collect();
+        assert_eq!(Ok(expected), spanned);
+    }
 
-        let formatted = super::format("(8 + 9 + 9)*2").unwrap();
-        assert_eq!("(8 + 9 + 9) * 2", formatted);
+    #[test]
+    fn can_tokenize_minus_after_operands() {
+        let sub = |lhs: Token| vec![lhs, Token::Sub, Token::LiteralNum(2.0)];
+        let ident = Token::Identifier(String::from("x"));
+        assert_eq!(Ok(sub(ident)), tokenize("x-2"));
+        assert_eq!(Ok(sub(Token::LiteralNum(5.0))), tokenize("5-2"));
+        assert_eq!(Ok(sub(Token::Pi)), tokenize("pi−2"));
+        let tokens = tokenize("(1)-2").unwrap();
+        assert_eq!(sub(Token::CloseParen), tokens[2..]);
+        let tokens = tokenize("(-2)").unwrap();
+        assert_eq!(Token::LiteralNum(-2.0), tokens[1]);
+        assert_eq!(Ok(vec![Token::LiteralNum(-2.0)]), tokenize("-2"));
+        let tokens = tokenize("1 * -2").unwrap();
+        assert_eq!(Token::LiteralNum(-2.0), tokens[2]);
 
-        let minified = super::minify("let calc= ( x, ) =>sin (90)").unwrap();
-        assert_eq!("let calc=(x)=>sin(90)", minified);
+        let mut vm = VM::new();
+        assert_eq!(None, compute(&mut vm, "let x = 10"));
+        assert_eq!(Some(8.0), compute(&mut vm, "x-2"));
+        assert_eq!(Some(3.0), compute(&mut vm, "5-2"));
+        assert_eq!(Some(9.0), compute(&mut vm, "(x + 1)-2"));
+        assert_eq!(Some(-2.0), compute(&mut vm, "(-2)"));
+    }
 
-        let minified = super::minify("example(1 - 1)").unwrap();
-        assert_eq!("example(1-1)", minified);
+    #[test]
+    fn can_tokenize_with_aliases() {
+        let mut aliases = lexer::AliasTable::new();
+        aliases.insert("raiz", Token::Sqrt);
+        aliases.insert("·", Token::Mul);
+        let tokens: Vec<_> =
+            lexer::tokenize_with_aliases(parser::Bite::new("raiz(9) · 2"), &aliases)
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(tokens, tokenize("sqrt(9) * 2").unwrap());
 
-        let minified = super::minify("(8 + 9 + 9)*2").unwrap();
-        assert_eq!("(8+9+9)*2", minified);
+        let mut vm = VM::new();
+        let source = parser::Bite::new("raiz(9)");
+        let tokens: Vec<_> = lexer::tokenize_with_aliases(source, &aliases)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let program = Compiler::new(&tokens).compile().unwrap();
+        vm.run(&program).unwrap();
+        assert_eq!(Some(3.0), vm.pop_result());
 
-        let indented = super::format_pretty(
-            "let fib = (x) => { if (x < 2) { 1 } else { fib(x - 2) + fib(x - 1) }",
-        )
-        .unwrap();
-        let expected = r#"
-let fib = (x) => {
-    if (x < 2) {
-        1
-    } else {
-        fib(x - 2) + fib(x - 1)
+        // without the table the alias is just an identifier
+        let tokens = tokenize("raiz(9)").unwrap();
+        assert_eq!(Token::Identifier(String::from("raiz")), tokens[0]);
     }
-}"#;
-        assert_eq!(expected.trim(), indented.trim());
+
+    #[test]
+    fn can_display_tokens_in_errors() {
+        let err = compile("(3) 2 - 1").unwrap_err();
+        assert!(err.contains("remaining tokens: '2 - 1'"), "{err}");
+        assert!(!err.contains("LiteralNum"), "{err}");
+
+        let tokens = tokenize("let f = (x) => x ≥ \"a\" mod 2.5").unwrap();
+        let rendered: Vec<_> = tokens.iter().map(|token| token.to_string()).collect();
+        assert_eq!("let f = ( x ) => x >= \"a\" % 2.5", rendered.join(" "));
     }
 
     #[test]
-    fn can_pretty_print_large_fns() {
-        let indented = super::format_pretty(
+    fn can_reject_invalid_params() {
+        let err = compile("let f = (x, x) => x").unwrap_err();
+        assert!(err.contains("duplicate parameter 'x'"), "{err}");
+        assert!(compile("let f = (, x) => x").is_err());
+        assert!(compile("let f = (x, y) => x").is_ok());
+        assert!(compile("(x)").is_ok());
+    }
+
+    #[test]
+    fn can_hash_programs() {
+        use crate::vm::ProgramKey;
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::HashSet;
+        use std::hash::{Hash, Hasher};
+
+        fn hash_of(program: &[Instruction]) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            ProgramKey(program).hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let a = compile("let f = (x) => x * 2; f(3)").unwrap();
+        let b = compile("let f = (x) => x*2; f(3)").unwrap();
+        let c = compile("let f = (x) => x * 2; f(4)").unwrap();
+        assert_eq!(ProgramKey(&a), ProgramKey(&b));
+        assert_eq!(hash_of(&a), hash_of(&b));
+        assert_ne!(ProgramKey(&a), ProgramKey(&c));
+        assert_ne!(hash_of(&a), hash_of(&c));
+        assert_eq!(
+            hash_of(&[Instruction::Push(0.0)]),
+            hash_of(&[Instruction::Push(-0.0)])
+        );
+
+        let programs: HashSet<_> = [&a, &b, &c].into_iter().map(|x| ProgramKey(x)).collect();
+        assert_eq!(2, programs.len());
+    }
+
+    #[test]
+    fn can_run_with_bindings() {
+        let mut vm = VM::new();
+        let program = compile("x^2").unwrap();
+        let results: Vec<_> = [0.0, 1.0, 2.0, 3.0]
+            .into_iter()
+            .map(|x| {
+                vm.run_with_bindings(&program, &[("x", x)]).unwrap();
+                vm.pop_result().unwrap()
+            })
+            .collect();
+        assert_eq!(vec![0.0, 1.0, 4.0, 9.0], results);
+
+        let program = compile("let y = x + 1; y").unwrap();
+        vm.run_with_bindings(&program, &[("x", 1.0)]).unwrap();
+        assert_eq!(Some(2.0), vm.pop_result());
+        assert_eq!(0.0, compute(&mut vm, "x + y").unwrap());
+    }
+
+    #[test]
+    fn can_eval_samples() {
+        let mut vm = VM::new();
+        let program = compile("x^2").unwrap();
+        assert_eq!(
+            vec![0.0, 1.0, 4.0],
+            eval_samples(&mut vm, &program, "x", 0.0, 2.0, 3)
+        );
+        assert_eq!(
+            vec![4.0, 1.0, 0.0],
+            eval_samples(&mut vm, &program, "x", 2.0, 0.0, 3)
+        );
+        assert_eq!(vec![1.0], eval_samples(&mut vm, &program, "x", 1.0, 5.0, 1));
+        assert!(eval_samples(&mut vm, &program, "x", 0.0, 1.0, 0).is_empty());
+
+        vm.set_strict_math(true);
+        let program = compile("1 / x").unwrap();
+        let samples = eval_samples(&mut vm, &program, "x", -1.0, 1.0, 3);
+        assert_eq!(-1.0, samples[0]);
+        assert!(samples[1].is_nan());
+        assert_eq!(1.0, samples[2]);
+    }
+
+    #[test]
+    fn can_get_function() {
+        let mut vm = VM::new();
+        compute(&mut vm, "let f=(x,y)=>x+y");
+        let info = vm.get_function("f").unwrap();
+        assert_eq!(vec!["x", "y"], info.params);
+        assert_eq!("x + y", info.body);
+
+        compute(&mut vm, "let g = (n) => f(n, 1) * sin(n)^2");
+        let info = vm.get_function("g").unwrap();
+        assert_eq!(vec!["n"], info.params);
+        assert_eq!("f(n, 1) * sin(n)^2", info.body);
+
+        compute(&mut vm, "let h = () => (a) => a - 1");
+        let info = vm.get_function("h").unwrap();
+        assert!(info.params.is_empty());
+        assert_eq!("(a) => a - 1", info.body);
+
+        compute(&mut vm, "let x = 3");
+        assert!(vm.get_function("x").is_none());
+        assert!(vm.get_function("missing").is_none());
+    }
+
+    #[test]
+    fn can_decompile_programs() {
+        fn round_trip(input: &str) -> String {
+            let program = compile(input).unwrap();
+            let expression = decompiler::decompile(&program).unwrap();
+            pretty::pretty_print(expression, pretty::PrettyFormat::Spaced, Default::default())
+        }
+
+        assert_eq!("3 - sin(90)", round_trip("3 - sin(90)"));
+        let inputs = [
+            "let f = (x, y) => x * (y + 1); f(2, 3)",
+            "if (x > 1) { x } else { 0 - x }",
+            "let count = (i) => if (i > 0) { count(i - 1) }",
+            "{ let a = [1, 2]; map(a, (x) => x^2) }",
+            "reduce(1..=3, (a, b) => a + b, 0)",
+        ];
+        for input in inputs {
+            assert_eq!(super::format(input).unwrap(), round_trip(input));
+        }
+
+        let swapped = [
+            Instruction::Push(10.0),
+            Instruction::Push(3.0),
+            Instruction::Swap,
+            Instruction::Sub,
+        ];
+        let expression = decompiler::decompile(&swapped).unwrap();
+        let spaced = pretty::PrettyFormat::Spaced;
+        assert_eq!(
+            "3 - 10",
+            pretty::pretty_print(expression, spaced, Default::default())
+        );
+
+        assert!(decompiler::decompile(&[Instruction::Add]).is_err());
+        assert!(decompiler::decompile(&[Instruction::Enter]).is_err());
+    }
+
+    #[test]
+    fn can_run_scripts() {
+        let mut vm = VM::new();
+        let script = "let f = (x) => x * 2\n\nf(21)\n";
+        assert_eq!(Ok(Some(42.0)), run_script(&mut vm, script));
+        assert_eq!(Some(6.0), compute(&mut vm, "f(3)"));
+
+        let err = run_script(&mut vm, "1 + 1\nlet = 2").unwrap_err();
+        assert!(err.starts_with("line 2: "), "{err}");
+
+        // a definition continues until its brackets close
+        let script = "let g = (x) => {\n  x + 1\n}\n\ng(2)";
+        assert_eq!(Ok(Some(3.0)), run_script(&mut vm, script));
+        let script = "let h = max(\n  1,\n  2\n)\nh * 2";
+        assert_eq!(Ok(Some(4.0)), run_script(&mut vm, script));
+        let err = run_script(&mut vm, "1\nlet k = (x) => {\n  x +\n}").unwrap_err();
+        assert!(err.starts_with("line 2: "), "{err}");
+    }
+
+    #[test]
+    fn can_balance_scopes() {
+        let mut vm = VM::new();
+        assert_eq!(1, vm.scope_depth());
+        assert_eq!(0, vm.stack_depth());
+
+        let program = compile("let f = (x) => { let y = { x + 1 }; { y * 2 } }; { f(1) + { 2 } }");
+        vm.run(&program.unwrap()).unwrap();
+        assert_eq!(1, vm.scope_depth());
+        assert_eq!(1, vm.stack_depth());
+        assert_eq!(Some(6.0), vm.pop_result());
+    }
+
+    #[test]
+    fn can_clear_residue_between_computes() {
+        let mut vm = VM::new();
+        assert_eq!(Some(2.0), super::compute(&mut vm, "{ 1; 2 }"));
+        assert_eq!(0, vm.stack_depth());
+        assert_eq!(None, super::compute(&mut vm, "if (0) { 5 }"));
+    }
+
+    #[test]
+    fn can_use_if_as_statement() {
+        let mut vm = VM::new();
+        vm.set_debug_checks(true);
+        assert_eq!(None, compute(&mut vm, "if (0) { 5 }"));
+        assert_eq!(None, compute(&mut vm, "if (1) { 5 }"));
+        assert_eq!(None, compute(&mut vm, "if (1) { 5; 6 }"));
+        assert_eq!(Some(8.0), compute(&mut vm, "if (0) { 5 } else { 8 }"));
+        assert_eq!(
+            Some(3.0),
+            compute(&mut vm, "{ let x = 0; if (1) { x := 3 }; x }")
+        );
+        assert_eq!(Some(2.0), compute(&mut vm, "{ if (1) { 5 }; 2 }"));
+
+        let err = compile("let x = if (0) { 5 }").unwrap_err();
+        assert!(err.contains("'if' without 'else' has no value"), "{err}");
+        let err = compile("{ let x = 0; x := if (1) { 5 } }").unwrap_err();
+        assert!(err.contains("'if' without 'else' has no value"), "{err}");
+        assert_eq!(None, compute(&mut vm, "let x = if (0) { 5 } else { 8 }"));
+        assert_eq!(Some(8.0), compute(&mut vm, "x"));
+    }
+
+    #[test]
+    fn can_chain_else_if() {
+        let mut vm = VM::new();
+        vm.set_debug_checks(true);
+        compute(
+            &mut vm,
+            "let choose = (a, b) => if (a) { 1 } else if (b) { 2 } else { 3 }",
+        );
+        assert_eq!(Some(1.0), compute(&mut vm, "choose(1, 1)"));
+        assert_eq!(Some(2.0), compute(&mut vm, "choose(0, 1)"));
+        assert_eq!(Some(3.0), compute(&mut vm, "choose(0, 0)"));
+        assert_eq!(
+            Some(4.0),
+            compute(
+                &mut vm,
+                "if (0) { 1 } else if (0) { 2 } else if (1) { 4 } else { 3 }"
+            )
+        );
+
+        // without a final 'else' the chain is a statement, like a lone 'if'
+        assert_eq!(None, compute(&mut vm, "if (1) { 1 } else if (0) { 2 }"));
+        assert_eq!(None, compute(&mut vm, "if (0) { 1 } else if (1) { 2 }"));
+        let err = compile("let x = if (0) { 1 } else if (1) { 2 }").unwrap_err();
+        assert!(err.contains("'if' without 'else' has no value"), "{err}");
+
+        assert_eq!(
+            Ok("if (a) { 1 } else if (b) { 2 } else { 3 }".to_string()),
+            super::format("if (a) {1} else if (b) {2} else {3}")
+        );
+        let indented = super::format_pretty("if (a) {1} else if (b) {2} else {3}").unwrap();
+        let expected = r#"
+if (a) {
+    1
+} else if (b) {
+    2
+} else {
+    3
+}"#;
+        assert_eq!(expected.trim(), indented.trim());
+    }
+
+    #[test]
+    fn can_restore_scopes_after_error() {
+        let mut vm = VM::new();
+        vm.set_strict_math(true);
+        let program = compile("{ let a = 5; { a / 0 } }").unwrap();
+        assert!(vm.run(&program).is_err());
+        assert_eq!(1, vm.scope_depth());
+
+        assert_eq!(0.0, compute(&mut vm, "a").unwrap());
+        assert_eq!(vec!["missing variable 'a'"], vm.take_warnings());
+    }
+
+    #[test]
+    fn can_require_statement_separators() {
+        let err = compile("{ let x = 1 let y = 2 }").unwrap_err();
+        assert!(err.contains("expected ';' between statements"), "{err}");
+        let err = compile("let f = () => { sin(1); sin(2) cos(3) }").unwrap_err();
+        assert!(err.contains("expected ';' between statements"), "{err}");
+
+        let mut vm = VM::new();
+        assert_eq!(
+            3.0,
+            compute(&mut vm, "{ let x = 1; let y = 2; x + y; }").unwrap()
+        );
+
+        // a known limitation: '1 x' reads as the implicit multiplication '1x', like '3x'
+        let input = "{ let x = 1 x + 1 }";
+        assert_eq!(
+            Ok("{ let x = (1 * x) + 1 }".to_string()),
+            super::format(input)
+        );
+        let options = compiler::CompileOptions {
+            implicit_multiplication: false,
+            ..Default::default()
+        };
+        let err = compile_with(input, options).unwrap_err();
+        assert!(err.contains("expected ';' between statements"), "{err}");
+    }
+
+    #[test]
+    fn can_compute_gamma_and_beta() {
+        let mut vm = VM::new();
+        assert_eq!(24.0, compute(&mut vm, "gamma(5)").unwrap().to_fixed(8));
+        let sqrt_pi = std::f64::consts::PI.sqrt();
+        assert_eq!(
+            sqrt_pi.to_fixed(8),
+            compute(&mut vm, "gamma(0.5)").unwrap().to_fixed(8)
+        );
+        assert_eq!(
+            (-2.0 * sqrt_pi).to_fixed(8),
+            compute(&mut vm, "gamma(0 - 0.5)").unwrap().to_fixed(8)
+        );
+        assert_eq!(
+            (1.0 / 12.0).to_fixed(8),
+            compute(&mut vm, "beta(2, 3)").unwrap().to_fixed(8)
+        );
+
+        for input in ["gamma(0)", "gamma(0 - 2)", "beta(0, 1)"] {
+            assert!(vm.run(&compile(input).unwrap()).is_err(), "{input}");
+        }
+        assert_eq!(
+            "gamma(x) * beta(1, 2)",
+            super::format("gamma(x)*beta(1,2)").unwrap()
+        );
+
+        // outside of a call, 'beta' and 'gamma' are free to use as names
+        let beta = compute(&mut vm, "let beta = 0.5; beta(beta * 2, 1)");
+        assert_eq!(1.0, beta.unwrap().to_fixed(8));
+        let gamma = compute(&mut vm, "let gamma = 3; gamma (gamma)");
+        assert_eq!(2.0, gamma.unwrap().to_fixed(8));
+        assert_eq!(Some(0.5), compute(&mut vm, "let betas = 0.5; betas"));
+        assert_eq!(Some(3.0), compute(&mut vm, "let gammas = 3; gammas"));
+    }
+
+    #[test]
+    fn can_compute_erf() {
+        let mut vm = VM::new();
+        let cases = [
+            ("erf(0)", 0.0),
+            ("erfc(0)", 1.0),
+            ("erf(0.5)", 0.520500),
+            ("erf(1)", 0.842701),
+            ("erf(0 - 1)", -0.842701),
+            ("erf(2)", 0.995322),
+            ("erfc(1)", 0.157299),
+            ("erfc(0 - 1)", 1.842701),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                expected,
+                compute(&mut vm, input).unwrap().to_fixed(6),
+                "{input}"
+            );
+        }
+        assert_eq!(
+            compute(&mut vm, "erf(0.3)").unwrap(),
+            -compute(&mut vm, "erf(0 - 0.3)").unwrap()
+        );
+
+        // the tail keeps its relative precision rather than cancelling to 0
+        for (input, expected) in [("erfc(5)", 1.537_459_8e-12), ("erfc(10)", 2.088_487_6e-45)] {
+            let result = compute(&mut vm, input).unwrap();
+            assert!(
+                ((result - expected) / expected).abs() < 1e-6,
+                "{input} = {result}"
+            );
+        }
+        assert_eq!(Some(2.0), compute(&mut vm, "erfc(0 - 10)"));
+        assert_eq!("erfc(x)", super::format("erfc(x)").unwrap());
+
+        // names merely starting with 'erf' are still identifiers
+        assert_eq!(Some(1.0), compute(&mut vm, "let erfx = 1; erfx"));
+        assert_eq!(Some(2.0), compute(&mut vm, "let errfunc = 2; errfunc"));
+    }
+
+    #[test]
+    fn can_call_native_functions() {
+        let mut vm = VM::new();
+        assert_eq!(212.0, compute(&mut vm, "toFahrenheit(100)").unwrap());
+        assert_eq!(0.0, compute(&mut vm, "toCelsius(32)").unwrap());
+        assert_eq!(
+            std::f64::consts::PI,
+            compute(&mut vm, "toRadians(180)").unwrap()
+        );
+        assert_eq!(
+            100.0,
+            compute(&mut vm, "toCelsius(toFahrenheit(100))")
+                .unwrap()
+                .round()
+        );
+
+        vm.register_native("hypot", 2, |args| Ok(args[0].hypot(args[1])));
+        assert_eq!(5.0, compute(&mut vm, "hypot(3, 4)").unwrap());
+        assert!(vm.run(&compile("hypot(3)").unwrap()).is_err());
+
+        assert_eq!(
+            1.0,
+            compute(&mut vm, "let toCelsius = (f) => 1; toCelsius(32)").unwrap()
+        );
+    }
+
+    #[test]
+    fn can_compute_min_max() {
+        let mut vm = VM::new();
+        assert_eq!(5.0, compute(&mut vm, "max([3, 1, 4, 1, 5])").unwrap());
+        assert_eq!(1.0, compute(&mut vm, "min([3, 1, 4])").unwrap());
+        assert_eq!(4.0, compute(&mut vm, "max(3, 1, 4)").unwrap());
+        assert_eq!(2.0, compute(&mut vm, "min(2)").unwrap());
+        assert_eq!(9.0, compute(&mut vm, "let a = 1..10; max(a)").unwrap());
+
+        assert!(vm.run(&compile("max([])").unwrap()).is_err());
+        assert!(compile("min()").is_err());
+        assert_eq!(
+            "max(a, 1) + min([1, 2])",
+            super::format("max(a,1)+min([1,2])").unwrap()
+        );
+
+        // names merely starting with 'min' or 'max' are still identifiers
+        assert_eq!(Some(1.0), compute(&mut vm, "let minutes = 1; minutes"));
+        assert_eq!(Some(2.0), compute(&mut vm, "let maximum = 2; maximum"));
+        assert_eq!(Some(3.0), compute(&mut vm, "let minimal = 3; minimal"));
+    }
+
+    #[test]
+    fn can_index_and_assign_arrays() {
+        let mut vm = VM::new();
+        assert_eq!(
+            9.0,
+            compute(&mut vm, "let a = [1, 2, 3]; a[1] = 9; a[1]").unwrap()
+        );
+        assert_eq!(
+            12.0,
+            compute(&mut vm, "a[0] + a[2] + a[1 + 0] - 1").unwrap()
+        );
+        assert_eq!(3.0, compute(&mut vm, "a[2] = a[2] * 1; a[2]").unwrap());
+
+        for input in [
+            "a[3] = 1",
+            "a[0 - 4] = 1",
+            "a[0.5] = 1",
+            "a[3]",
+            "let b = 2; b[0] = 1",
+        ] {
+            assert!(vm.run(&compile(input).unwrap()).is_err(), "{input}");
+        }
+        assert_eq!(
+            "a[i + 1] = a[i] * 2",
+            super::format("a[i+1]=a[i]*2").unwrap()
+        );
+    }
+
+    #[test]
+    fn can_chain_postfix_indexing() {
+        let mut vm = VM::new();
+        assert_eq!(3.0, compute(&mut vm, "[[1, 2], [3, 4]][1][0]").unwrap());
+        assert_eq!(
+            4.0,
+            compute(&mut vm, "let m = [[1, 2], [3, 4]]; m[1][1]").unwrap()
+        );
+        assert_eq!(
+            10.0,
+            compute(
+                &mut vm,
+                "let funcs = [(x) => x + 1, (x) => x * 2]; funcs[1](5)"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            8.0,
+            compute(&mut vm, "let g = [(a) => (b) => b * 2]; g[0](3)(4)").unwrap()
+        );
+        assert_eq!(6.0, compute(&mut vm, "(1 + 2)(2)").unwrap());
+
+        let err = vm.run(&compile("let n = 5; n[0]").unwrap()).unwrap_err();
+        assert!(err.contains("not an array"), "{err}");
+        assert_eq!(
+            "m[1][0] + f[0](x)",
+            super::format("m[1][0]+f[0](x)").unwrap()
+        );
+    }
+
+    #[test]
+    fn can_return_no_result_silently() {
+        let mut vm = VM::new();
+        assert_eq!(None, super::compute(&mut vm, "let x = 1"));
+        assert!(vm.take_warnings().is_empty());
+        assert_eq!(None, vm.pop_result());
+        assert_eq!(Some(1.0), super::compute(&mut vm, "x"));
+    }
+
+    #[test]
+    fn can_tolerate_trailing_semicolons() {
+        let mut vm = VM::new();
+        assert!(compile("let x = 1;").is_ok());
+        assert_eq!(None, super::compute(&mut vm, "let x = 1;"));
+        assert_eq!(Some(2.0), super::compute(&mut vm, "x + 1;"));
+        assert_eq!(Some(3.0), super::compute(&mut vm, "let y = 2; x + y;"));
+        assert!(compile("x + 1;;").is_err());
+    }
+
+    #[test]
+    fn can_view_stack() {
+        use crate::vm::StackItem;
+
+        let mut vm = VM::new();
+        vm.run(&[Instruction::Push(1.0), Instruction::Push(2.0)])
+            .unwrap();
+        let expected = vec![StackItem::Number(1.0), StackItem::Number(2.0)];
+        assert_eq!(expected, vm.stack_view());
+        vm.run(&[Instruction::Add]).unwrap();
+        assert_eq!(vec![StackItem::Number(3.0)], vm.stack_view());
+
+        vm.clear_stack();
+        vm.run(&compile("(x, y) => x; [1, \"a\"]; toCelsius").unwrap())
+            .unwrap();
+        let expected = vec![
+            StackItem::Routine { arity: 2 },
+            StackItem::Array(vec![StackItem::Number(1.0), StackItem::Str("a".into())]),
+            StackItem::Routine { arity: 1 },
+        ];
+        assert_eq!(expected, vm.stack_view());
+    }
+
+    #[test]
+    fn can_pop_structured_values() {
+        use crate::vm::StackItem;
+
+        let mut vm = VM::new();
+        vm.run(&compile("[1, 2, 3]").unwrap()).unwrap();
+        let expected = StackItem::Array(vec![
+            StackItem::Number(1.0),
+            StackItem::Number(2.0),
+            StackItem::Number(3.0),
+        ]);
+        assert_eq!(Some(expected), vm.pop_value());
+        assert_eq!(None, vm.pop_value());
+
+        vm.run(&compile("(x) => x; \"a\"; 4").unwrap()).unwrap();
+        assert_eq!(Some(StackItem::Number(4.0)), vm.pop_value());
+        assert_eq!(Some(StackItem::Str("a".into())), vm.pop_value());
+        assert_eq!(Some(StackItem::Routine { arity: 1 }), vm.pop_value());
+    }
+
+    #[test]
+    fn can_compute_through_prelude() {
+        use crate::prelude::*;
+
+        let mut vm = VM::new();
+        assert_eq!(Some(7.0), compute(&mut vm, "1 + 2 * 3"));
+        let program: Vec<Instruction> = compile("1 + 2").unwrap();
+        assert_eq!(Ok(()), vm.run(&program));
+        let err: CalcError = compile("1 +").unwrap_err();
+        assert!(!err.is_empty());
+        assert_eq!(Ok("1 + 2".to_string()), format("1+2"));
+    }
+
+    #[test]
+    fn can_compute_greek_identifiers() {
+        let mut vm = VM::new();
+        assert_eq!(1.0, compute(&mut vm, "let θ = 90; sin(θ)").unwrap());
+        assert_eq!(
+            6.0,
+            compute(&mut vm, "let α = 2; let β = 3; α * β").unwrap()
+        );
+        assert_eq!(8.0, compute(&mut vm, "let λ = (x) => x * 2; λ(4)").unwrap());
+        assert_eq!(std::f64::consts::PI, compute(&mut vm, "π").unwrap());
+        assert_eq!(Ok(vec![Token::Pi]), tokenize("π"));
+    }
+
+    #[test]
+    fn can_compute_subscripted_identifiers() {
+        let mut vm = VM::new();
+        assert_eq!(6.0, compute(&mut vm, "let x_1 = 5; x_1 + 1").unwrap());
+        assert_eq!(
+            7.0,
+            compute(&mut vm, "let v_0 = 2; let a_max = 5; v_0 + a_max").unwrap()
+        );
+        assert_eq!(
+            Ok(vec![
+                Token::Identifier("x_1".to_string()),
+                Token::Sub,
+                Token::Identifier("x_12".to_string())
+            ]),
+            tokenize("x_1 - x_12")
+        );
+
+        let options = pretty::PrettyOptions {
+            unicode_subscripts: true,
+            ..Default::default()
+        };
+        let spaced = pretty::PrettyFormat::Spaced;
+        let input = "let f = (x_1) => x_1 + a_max + v_10; f(x_2)";
+        assert_eq!(
+            "let f = (x₁) => x₁ + a_max + v₁₀; f(x₂)",
+            format_with(input, spaced, options).unwrap()
+        );
+        assert_eq!(input, super::format(input).unwrap());
+    }
+
+    #[test]
+    fn can_format_unicode_operators() {
+        let tokens = tokenize("2 × 3 ÷ 4 − 1").unwrap();
+        let expected = [
+            Token::LiteralNum(2.0),
+            Token::Mul,
+            Token::LiteralNum(3.0),
+            Token::Div,
+            Token::LiteralNum(4.0),
+            Token::Sub,
+            Token::LiteralNum(1.0),
+        ];
+        assert_eq!(&expected, tokens.as_slice());
+
+        let options = pretty::PrettyOptions {
+            unicode_operators: true,
+            ..Default::default()
+        };
+        let spaced = pretty::PrettyFormat::Spaced;
+        let minified = pretty::PrettyFormat::Minified;
+        let format = |input, which| format_with(input, which, options).unwrap();
+        assert_eq!("2 × 3 ÷ 4", format("2 × 3 ÷ 4", spaced));
+        assert_eq!("2 × 3 ÷ 4", format("2*3/4", spaced));
+        assert_eq!("5 − −1", format("5 − −1", spaced));
+        assert_eq!("5−−1", format("5 - -1", minified));
+        assert_eq!("(2 * 3 / 4) - 1", super::format("2 × 3 ÷ 4 − 1").unwrap());
+
+        let mut vm = VM::new();
+        assert_eq!(6.0, compute(&mut vm, "5 − −1").unwrap());
+        assert_eq!(4.0, compute(&mut vm, "5 − 1").unwrap());
+        assert_eq!(0.0 - 3.0, compute(&mut vm, "−1 × 3").unwrap());
+    }
+
+    #[test]
+    fn can_format_preserving_groups() {
+        let options = pretty::PrettyOptions {
+            preserve_groups: true,
+            ..Default::default()
+        };
+        let format = |input| format_with(input, pretty::PrettyFormat::Spaced, options);
+        assert_eq!(Ok("2 * (3 + 4)".to_string()), format("2*(3+4)"));
+        assert_eq!(Ok("(2 * 3) + 4".to_string()), format("(2*3)+4"));
+        assert_eq!(Ok("((1))".to_string()), format("((1))"));
+        assert_eq!(Ok("1".to_string()), super::format("((1))"));
+
+        let tokens = tokenize("(2 * 3) + 4").unwrap();
+        let mut compiler = Compiler::new(&tokens);
+        compiler.set_preserve_groups(true);
+        assert_eq!(compile("(2 * 3) + 4"), compiler.compile());
+    }
+
+    #[test]
+    fn can_parse_separated_lists() {
+        assert_eq!(Ok("f(a, b, c)".to_string()), super::format("f(a,b,c)"));
+        assert_eq!(Ok("f()".to_string()), super::format("f( )"));
+        assert_eq!(Ok("f(a, b)".to_string()), super::format("f(a, b,)"));
+        assert_eq!(Ok("[]".to_string()), super::format("[ ]"));
+        assert_eq!(Ok("[1, 2]".to_string()), super::format("[1, 2,]"));
+        assert!(super::format("f(a,, b)").is_err());
+        assert!(super::format("f(a b)").is_err());
+
+        let mut vm = VM::new();
+        let program = "let f = (a, b, c,) => a * b + c; f(2, 3, 4,)";
+        assert_eq!(Some(10.0), compute(&mut vm, program));
+        assert_eq!(Some(5.0), compute(&mut vm, "let g = () => 5; g()"));
+        assert_eq!(Some(3.0), compute(&mut vm, "let a, b, = (1, 2); a + b"));
+    }
+
+    #[test]
+    fn can_compute_assignment_expressions() {
+        let mut vm = VM::new();
+        assert_eq!(Some(6.0), compute(&mut vm, "(x := 5) + 1"));
+        assert_eq!(Some(5.0), compute(&mut vm, "x"));
+        assert_eq!(None, compute(&mut vm, "let y = (x := 2) * 10"));
+        assert_eq!(Some(22.0), compute(&mut vm, "x + y"));
+        assert_eq!(Some(3.0), compute(&mut vm, "x := 3"));
+        assert_eq!(None, compute(&mut vm, "let z = 1"));
+
+        let program = compile("let y = (x := 5) + 1").unwrap();
+        let decompiled = decompiler::decompile(&program).unwrap();
+        let formatted =
+            pretty::pretty_print(decompiled, pretty::PrettyFormat::Spaced, Default::default());
+        assert_eq!("let y = (x := 5) + 1", formatted);
+        assert_eq!(Ok("x := 5".to_string()), super::format("x:=5"));
+    }
+
+    #[test]
+    fn can_compute_powers_alike() {
+        let mut vm = VM::new();
+        assert_eq!(Some(1024.0), compute(&mut vm, "pow(2, 10)"));
+        assert_eq!(Some(1024.0), compute(&mut vm, "2^10"));
+        assert_eq!(Some(1024.0), compute(&mut vm, "2¹⁰"));
+        assert_eq!(compile("2^10"), compile("pow(2, 10)"));
+
+        assert_eq!(Some(512.0), compute(&mut vm, "2^3^2"));
+        assert_eq!(Some(512.0), compute(&mut vm, "2^3²"));
+        assert_eq!(Some(64.0), compute(&mut vm, "(2^3)^2"));
+        assert_eq!(Some(2.0), compute(&mut vm, "2^3^2 % 5"));
+        assert_eq!(Some(1.0), compute(&mut vm, "10 % 3^2"));
+
+        assert_eq!(Ok("2^3^2".to_string()), super::format("2^(3^2)"));
+        assert_eq!(Ok("(2^3)^2".to_string()), super::format("(2^3)^2"));
+        assert_eq!(Ok("(10 % 3)^2".to_string()), super::format("(10 % 3)^2"));
+
+        // names merely starting with 'pow' are still identifiers
+        assert_eq!(Some(2.0), compute(&mut vm, "let power = 2; power"));
+    }
+
+    #[test]
+    fn can_compute_superscript_powers() {
+        let mut vm = VM::new();
+        assert_eq!(Some(9.0), compute(&mut vm, "3²"));
+        assert_eq!(Some(8.0), compute(&mut vm, "2³"));
+        assert_eq!(None, compute(&mut vm, "let x = 2"));
+        assert_eq!(Some(2f64.powi(23)), compute(&mut vm, "x²³"));
+        assert_eq!(Some(13.0), compute(&mut vm, "3x² + 1"));
+        assert_eq!(Some(10.0), compute(&mut vm, "(x + 1)² + 1"));
+        assert_eq!(compile("x^2 - 1"), compile("x²-1"));
+
+        let tokens = tokenize("x²³").unwrap();
+        assert_eq!(Token::Superscript(23), tokens[1]);
+        assert_eq!("²³", tokens[1].to_string());
+    }
+
+    #[test]
+    fn can_assert_stack_empty() {
+        let mut vm = VM::new();
+        vm.set_debug_checks(true);
+        assert_eq!(Ok(()), vm.run(&compile("let x = 1 + 2").unwrap()));
+        assert_eq!(Ok(()), vm.run(&compile("{ 1; x * 2 }").unwrap()));
+        assert_eq!(Some(6.0), vm.pop_result());
+
+        // results left by earlier runs aren't this run's strays
+        assert_eq!(Ok(()), vm.run(&compile("1").unwrap()));
+        assert_eq!(Ok(()), vm.run(&compile("2").unwrap()));
+        assert_eq!(Some(2.0), vm.pop_result());
+        vm.clear_stack();
+
+        // a miscompiled program leaving an extra value under its result
+        let program = [
+            Instruction::Push(1.0),
+            Instruction::Push(2.0),
+            Instruction::Push(3.0),
+            Instruction::Add,
+        ];
+        let err = vm.run(&program).unwrap_err();
+        assert!(err.contains("1 stray value(s)"), "{err}");
+
+        vm.clear_stack();
+        vm.set_debug_checks(false);
+        assert_eq!(Ok(()), vm.run(&program));
+        assert_eq!(Some(5.0), vm.pop_result());
+    }
+
+    #[test]
+    fn can_eval_once() {
+        assert_eq!(Ok(Some(14.0)), eval("2 + 3 * 4"));
+        assert_eq!(Ok(None), eval("let x = 1"));
+        assert!(eval("(").is_err());
+        assert!(eval("[1, 2][5]").is_err());
+        // nothing carries over between calls
+        assert_eq!(Ok(Some(3.0)), eval("let x = 3; x"));
+        assert_eq!(Ok(Some(0.0)), eval("x"));
+    }
+
+    #[test]
+    fn can_propagate_constants() {
+        let options = compiler::CompileOptions {
+            optimize: true,
+            ..Default::default()
+        };
+        let program = compile_with("let x = 2; x * 3", options).unwrap();
+        let expected = vec![
+            Instruction::Push(2.0),
+            Instruction::Assign(String::from("x")),
+            Instruction::Push(6.0),
+        ];
+        assert_eq!(expected, program);
+        assert_eq!(
+            Ok(vec![Instruction::Push(7.0)]),
+            compile_with("1 + 2 * 3", options)
+        );
+
+        // reassigned, so 'x' can't be treated as a constant
+        let program = compile_with("let x = 2; let x = x + 1; x", options).unwrap();
+        assert!(program.contains(&Instruction::LoadLocal(String::from("x"))));
+        let mut vm = VM::new();
+        vm.run(&program).unwrap();
+        assert_eq!(Some(3.0), vm.pop_result());
+
+        // functions look 'x' up where they're called, and calls may reassign it
+        let program = compile_with("let x = 2; let f = () => x; f() + x", options).unwrap();
+        let load_x = Instruction::LoadLocal(String::from("x"));
+        assert!(program.contains(&load_x));
+        assert!(matches!(&program[2], Instruction::PushRoutine(body) if body.contains(&load_x)));
+        // folding leaves errors (and infinities) for the VM to report
+        assert_eq!(compile("1 / 0"), compile_with("1 / 0", options));
+    }
+
+    #[test]
+    fn can_warn_about_shadowing() {
+        let warnings = |input| {
+            let tokens = tokenize(input).unwrap();
+            let mut compiler = Compiler::new(&tokens);
+            compiler.compile().unwrap();
+            compiler.take_warnings()
+        };
+        let expected = vec!["'let x' reassigns a variable from an enclosing scope"];
+        assert_eq!(expected, warnings("let x = 1; { let x = 2 }"));
+        assert_eq!(expected, warnings("let f = (x) => { let x = x + 1; x }"));
+        assert!(warnings("let x = 1; { let y = 2 }").is_empty());
+        assert!(warnings("let x = 1; let x = 2; { x }").is_empty());
+        assert!(warnings("{ let y = 1 }; { let y = 2 }").is_empty());
+
+        // scoping is dynamic, so the inner 'let' overwrites the outer 'x' rather than hiding it
+        let mut vm = VM::new();
+        let program = super::compile_for(&mut vm, "let x = 1; { let x = 2 }; x").unwrap();
+        assert_eq!(expected, vm.take_warnings());
+        vm.run(&program).unwrap();
+        assert_eq!(Some(2.0), vm.pop_result());
+        vm.clear_stack();
+
+        assert_eq!(
+            Ok(Some(3.0)),
+            run_script(&mut vm, "1\nlet y = 1; { let y = 3 }; y")
+        );
+        let expected = vec!["'let y' reassigns a variable from an enclosing scope"];
+        assert_eq!(expected, vm.take_warnings());
+    }
+
+    #[test]
+    fn can_call_resolved_functions() {
+        let mut vm = VM::new();
+        compute(&mut vm, "let k = 10; let f = (x, y) => k * x + y");
+        let f = vm.resolve_function("f").unwrap();
+        assert_eq!(2, f.arity());
+        assert_eq!(Ok(Some(12.0)), vm.call_resolved(&f, &[1.0, 2.0]));
+        let sum: f64 = (0..1000)
+            .map(|x| vm.call_resolved(&f, &[x as f64, 1.0]).unwrap().unwrap())
+            .sum();
+        assert_eq!(10.0 * 499500.0 + 1000.0, sum);
+        assert_eq!(0, vm.stack_depth());
+        assert_eq!(1, vm.scope_depth());
+
+        // the handle keeps the function it resolved, but still sees current variables
+        compute(&mut vm, "let k = 100; let f = (x, y) => 0");
+        assert_eq!(Ok(Some(102.0)), vm.call_resolved(&f, &[1.0, 2.0]));
+
+        let err = vm.call_resolved(&f, &[1.0]).unwrap_err();
+        assert!(err.contains("f expects 2 argument(s), got 1"), "{err}");
+        compute(&mut vm, "let g = (x) => [1][x]");
+        let g = vm.resolve_function("g").unwrap();
+        assert!(vm.call_resolved(&g, &[5.0]).is_err());
+        assert_eq!(1, vm.scope_depth());
+        assert!(vm.resolve_function("k").is_none());
+        assert!(vm.resolve_function("nope").is_none());
+    }
+
+    #[test]
+    fn can_compare_non_numbers() {
+        let mut vm = VM::new();
+        assert_eq!(Some(1.0), compute(&mut vm, "[1, 2] == [1, 2]"));
+        assert_eq!(Some(0.0), compute(&mut vm, "[1, 2] == [1, 3]"));
+        assert_eq!(Some(0.0), compute(&mut vm, "[1, 2] == [1, 2, 3]"));
+        assert_eq!(Some(1.0), compute(&mut vm, "[1, 2] != [2, 1]"));
+        assert_eq!(Some(1.0), compute(&mut vm, "[[1], 2] == [[1], 2]"));
+        assert_eq!(Some(0.0), compute(&mut vm, "[1] == 1"));
+        assert_eq!(Some(1.0), compute(&mut vm, "(1, 2) == (1, 2)"));
+
+        compute(&mut vm, "let f = (x) => x; let g = (x) => x * 2");
+        let err = vm.run(&compile("f == g").unwrap()).unwrap_err();
+        assert!(err.contains("cannot compare functions"), "{err}");
+        let err = vm.run(&compile("f < 1").unwrap()).unwrap_err();
+        assert!(err.contains("cannot compare functions"), "{err}");
+    }
+
+    #[test]
+    fn can_compare_strings() {
+        let mut vm = VM::new();
+        assert_eq!(Some(1.0), compute(&mut vm, "\"ab\" == \"ab\""));
+        assert_eq!(Some(0.0), compute(&mut vm, "\"ab\" == \"abc\""));
+        assert_eq!(Some(1.0), compute(&mut vm, "\"ab\" != \"Ab\""));
+        assert_eq!(Some(0.0), compute(&mut vm, "1 == \"1\""));
+        assert_eq!(Some(1.0), compute(&mut vm, "1 != \"1\""));
+        assert_eq!(Some(0.0), compute(&mut vm, "[\"a\"] == \"a\""));
+        assert_eq!(Some(1.0), compute(&mut vm, "[\"a\", 1] == [\"a\", 1]"));
+        assert_eq!(Some(0.0), compute(&mut vm, "[1, 2] == [1, 3]"));
+    }
+
+    #[test]
+    fn can_list_builtins() {
+        use crate::compiler::BuiltinFunction;
+
+        let builtins = super::builtins();
+        let function = |name: &str, arities: &[usize]| BuiltinFunction {
+            name: name.to_string(),
+            arities: arities.to_vec(),
+        };
+        assert!(builtins.functions.contains(&function("sin", &[1])));
+        assert!(builtins.functions.contains(&function("rand", &[0])));
+        assert!(builtins.functions.contains(&function("pow", &[2])));
+        assert!(builtins.functions.contains(&function("reduce", &[3])));
+        assert!(builtins.functions.contains(&function("min", &[])));
+        assert!(builtins.functions.contains(&function("toRadians", &[1])));
+        assert_eq!(vec!["pi", "E"], builtins.constants);
+
+        // called, every listed keyword lexes back to itself, so the names can be inserted as typed
+        for name in builtins.functions.iter().map(|x| &x.name) {
+            let tokens = tokenize(&format!("{name}(")).unwrap();
+            assert!(tokens.len() >= 2, "{name}");
+            assert_eq!(name, &tokens[0].to_string());
+        }
+    }
+
+    #[test]
+    fn can_complete_identifiers() {
+        use crate::vm::{Completion, CompletionKind};
+
+        let completion = |name: &str, kind| Completion {
+            name: name.to_string(),
+            kind,
+        };
+        let mut vm = VM::new();
+        compute(
+            &mut vm,
+            "let velocity = 3; let vel = (x) => x; let speed = 1",
+        );
+        assert_eq!(
+            vec![
+                completion("vel", CompletionKind::Function),
+                completion("velocity", CompletionKind::Variable),
+            ],
+            vm.completions("vel")
+        );
+        assert!(vm
+            .completions("si")
+            .contains(&completion("sin", CompletionKind::Builtin)));
+        assert_eq!(
+            vec![completion("pi", CompletionKind::Constant)],
+            vm.completions("pi")
+        );
+        assert!(vm
+            .completions("to")
+            .contains(&completion("toCelsius", CompletionKind::Builtin)));
+
+        // a variable hiding a native is only listed once, as the variable
+        compute(&mut vm, "let toCelsius = 5");
+        let completions = vm.completions("toC");
+        assert_eq!(
+            vec![completion("toCelsius", CompletionKind::Variable)],
+            completions
+        );
+        assert!(vm.completions("").len() > super::builtins().functions.len());
+        assert!(vm.completions("zzz").is_empty());
+    }
+
+    #[test]
+    fn can_parse_arrow_bodies() {
+        let mut vm = VM::new();
+        compute(
+            &mut vm,
+            "let f = (x) => (x + 1); let g = (x) => { let y = x * 2; y + 1 }",
+        );
+        assert_eq!(Some(3.0), compute(&mut vm, "f(2)"));
+        assert_eq!(Some(5.0), compute(&mut vm, "g(2)"));
+        assert_eq!(
+            Some(4.0),
+            compute(&mut vm, "map([1, 2], (x) => (x * 2))[1]")
+        );
+
+        for source in [
+            "(x) =>",
+            "let f = (x) =>",
+            "let f = (x) => ;",
+            "map([1], (x) => )",
+        ] {
+            let err = compile(source).unwrap_err();
+            assert!(
+                err.contains("expected a function body after '=>'"),
+                "{source}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn can_report_errors_from_reverted_parses() {
+        for source in [
+            "(x, 1) => x",
+            "let f = (x, y + 1) => x",
+            "map([1], (2) => 2)",
+        ] {
+            let err = compile(source).unwrap_err();
+            assert!(
+                err.contains("function parameters must be names"),
+                "{source}: {err}"
+            );
+        }
+        let err = compile("(x, ) =>").unwrap_err();
+        assert!(err.contains("expected a function body after '=>'"), "{err}");
+
+        // an attempt that fails early doesn't mask a later error, nor fail a parse that succeeds
+        let err = compile("(1 + 2) +").unwrap_err();
+        assert!(!err.contains("function"), "{err}");
+        let mut vm = VM::new();
+        assert_eq!(
+            Some(3.0),
+            compute(&mut vm, "{ let x = 1; (x) + [(x), 2][1] }")
+        );
+    }
+
+    #[test]
+    fn can_limit_array_len() {
+        let mut vm = VM::new();
+        let err = vm.run(&compile("1..1000000000").unwrap()).unwrap_err();
+        assert!(err.contains("range 1..1000000000 is too large"), "{err}");
+        assert!(vm.run(&compile("0..(1/0)").unwrap()).is_err());
+        assert_eq!(Some(999_999.0), compute(&mut vm, "(0..1000000)[999999]"));
+
+        vm.set_max_array_len(3);
+        assert_eq!(Some(2.0), compute(&mut vm, "(0..3)[2]"));
+        let err = vm.run(&compile("0..=3").unwrap()).unwrap_err();
+        assert!(
+            err.contains("array of 4 elements exceeds the limit of 3"),
+            "{err}"
+        );
+        let err = vm.run(&compile("[1, 2, 3, 4]").unwrap()).unwrap_err();
+        assert!(
+            err.contains("array of 4 elements exceeds the limit of 3"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn can_evaluate_in_f32_precision() {
+        let mut vm = VM::new();
+        vm.set_precision(vm::Precision::F32);
+        let expected = (0..10).fold(0f32, |acc, _| acc + 0.1);
+        let result = compute(&mut vm, "reduce(1..=10, (acc, x) => acc + 0.1, 0)");
+        assert_eq!(Some(expected as f64), result);
+        assert_ne!(Some(1.0), result);
+        assert_eq!(Some((1f32 / 3.0) as f64), compute(&mut vm, "1 / 3"));
+        assert_eq!(Some((2f32).sqrt() as f64), compute(&mut vm, "sqrt(2)"));
+        assert_eq!(Some(0.1f32 as f64), compute(&mut vm, "[0.1, 0.2][0]"));
+
+        vm.set_precision(vm::Precision::F64);
+        let expected = (0..10).fold(0f64, |acc, _| acc + 0.1);
+        let result = compute(&mut vm, "reduce(1..=10, (acc, x) => acc + 0.1, 0)");
+        assert_eq!(Some(expected), result);
+    }
+
+    #[test]
+    fn can_print_values() {
+        use crate::vm::StackItem;
+
+        let mut vm = VM::new();
+        assert_eq!(Some(6.0), compute(&mut vm, "print(5) + 1"));
+        assert_eq!(vec![StackItem::Number(5.0)], vm.take_output());
+        assert!(vm.take_output().is_empty());
+
+        compute(&mut vm, "let f = (x) => print(x * 2); map([1, 2], f)");
+        assert_eq!(
+            vec![StackItem::Number(2.0), StackItem::Number(4.0)],
+            vm.take_output()
+        );
+        compute(&mut vm, "print([1, 2]); print(\"done\")");
+        assert_eq!(
+            vec![
+                StackItem::Array(vec![StackItem::Number(1.0), StackItem::Number(2.0)]),
+                StackItem::Str(String::from("done"))
+            ],
+            vm.take_output()
+        );
+        assert_eq!(Ok("print(x) + 1".to_string()), super::format("print(x)+1"));
+
+        // names merely starting with 'print' are still identifiers
+        assert_eq!(Some(2.0), compute(&mut vm, "let printer = 2; printer"));
+        assert_eq!(Some(3.0), compute(&mut vm, "print_count := 3"));
+    }
+
+    #[test]
+    fn can_declare_consts() {
+        let mut vm = VM::new();
+        assert_eq!(Some(3.0), compute(&mut vm, "let constant = 3; constant"));
+
+        let err = vm
+            .run(&compile("const c = 3; let c = 4").unwrap())
+            .unwrap_err();
+        assert!(err.contains("cannot reassign const 'c'"), "{err}");
+        assert_eq!(Some(3.0), compute(&mut vm, "c"));
+        assert!(vm.run(&compile("c := 5").unwrap()).is_err());
+        assert!(vm.run(&compile("const c = 5").unwrap()).is_err());
+        assert!(vm.run(&compile("{ let c = 5 }").unwrap()).is_err());
+        assert_eq!(Some(3.0), compute(&mut vm, "c"));
+
+        // destructuring onto a const assigns none of the names
+        assert_eq!(None, compute(&mut vm, "let d = 1"));
+        let err = vm.run(&compile("let d, c = (2, 4)").unwrap()).unwrap_err();
+        assert!(err.contains("cannot reassign const 'c'"), "{err}");
+        assert_eq!(Some(1.0), compute(&mut vm, "d"));
+
+        assert_eq!(Some(4.0), compute(&mut vm, "let x = 3; let x = 4; x"));
+        assert_eq!(
+            Some(8.0),
+            compute(&mut vm, "const xs = [1, 2]; const f = (c) => c * 2; f(4)")
+        );
+        assert!(vm.run(&compile("xs[0] = 3").unwrap()).is_err());
+        assert_eq!(Some(6.0), compute(&mut vm, "f(c)"));
+
+        let err = compile("const = 3").unwrap_err();
+        assert!(err.contains("expected a name after 'const'"), "{err}");
+        assert_eq!(Ok("const c = 3".to_string()), super::format("const c=3"));
+
+        // a const inside a function is local to each call, leaving the caller's 'k' alone
+        let mut vm = VM::new();
+        assert_eq!(
+            None,
+            compute(&mut vm, "let k = 0; let f = () => { const k = 1; k }")
+        );
+        assert_eq!(Some(1.0), compute(&mut vm, "f()"));
+        assert_eq!(Some(1.0), compute(&mut vm, "f()"));
+        assert_eq!(Some(0.0), compute(&mut vm, "k"));
+        assert_eq!(Some(5.0), compute(&mut vm, "k := 5"));
+    }
+
+    #[test]
+    fn can_index_from_end() {
+        let mut vm = VM::new();
+        assert_eq!(Some(30.0), compute(&mut vm, "[10, 20, 30][-1]"));
+        assert_eq!(Some(10.0), compute(&mut vm, "[10, 20, 30][-3]"));
+        assert_eq!(
+            Some(5.0),
+            compute(&mut vm, "let a = [1, 2]; a[-1] = 5; a[1]")
+        );
+
+        for source in ["[1, 2][5]", "[1, 2][2]", "[1, 2][-3]"] {
+            let err = vm.run(&compile(source).unwrap()).unwrap_err();
+            assert!(
+                err.contains("out of bounds for length 2"),
+                "{source}: {err}"
+            );
+        }
+        let err = vm.run(&compile("[1, 2][0.5]").unwrap()).unwrap_err();
+        assert!(err.contains("array index must be a whole number"), "{err}");
+    }
+
+    #[test]
+    fn can_compute_lerp_and_clamp01() {
+        let mut vm = VM::new();
+        assert_eq!(Some(5.0), compute(&mut vm, "lerp(0, 10, 0.5)"));
+        assert_eq!(Some(12.5), compute(&mut vm, "lerp(10, 20, 0.25)"));
+        assert_eq!(Some(30.0), compute(&mut vm, "lerp(10, 20, 2)"));
+        assert_eq!(Some(1.0), compute(&mut vm, "clamp01(1.5)"));
+        assert_eq!(Some(0.0), compute(&mut vm, "clamp01(-2)"));
+        assert_eq!(Some(0.25), compute(&mut vm, "clamp01(0.25)"));
+        assert_eq!(Some(10.0), compute(&mut vm, "lerp(0, 10, clamp01(3))"));
+
+        let program = compile("lerp(a, b, clamp01(t))").unwrap();
+        let expression = decompiler::decompile(&program).unwrap();
+        let source =
+            pretty::pretty_print(expression, pretty::PrettyFormat::Spaced, Default::default());
+        assert_eq!("lerp(a, b, clamp01(t))", source);
+
+        // names merely starting with 'lerp' or 'clamp01' are still identifiers
+        assert_eq!(Some(2.0), compute(&mut vm, "let lerped = 2; lerped"));
+        assert_eq!(
+            Some(3.0),
+            compute(&mut vm, "let clamp01_max = 3; clamp01_max")
+        );
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn can_send_vm_to_thread() {
+        let mut vm = VM::new();
+        compute(&mut vm, "x := 20").unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let result = compute(&mut vm, "x + 1 + rand() * 0");
+            (result, vm)
+        });
+        let (result, mut vm) = handle.join().unwrap();
+        assert_eq!(Some(21.0), result);
+        assert_eq!(Some(22.0), compute(&mut vm, "x + 2"));
+    }
+
+    #[test]
+    fn can_sample_normal_distribution() {
+        use crate::vm::StackItem;
+
+        let mean_of = |vm: &mut VM, sample: &str| {
+            let program =
+                format!("reduce(map(1..4000, (i) => {sample}), (sum, x) => sum + x, 0) / 4000");
+            compute(vm, &program).unwrap()
+        };
+        let mut vm = VM::new();
+        vm.set_seed(42);
+        assert!(mean_of(&mut vm, "rand_normal()").abs() < 0.1);
+        assert!((mean_of(&mut vm, "rand_normal(10, 2)") - 10.0).abs() < 0.2);
+
+        let spread = compute(&mut vm, "max(map(1..1000, (i) => rand_normal(0, 0.5) ^ 2))").unwrap();
+        assert!(spread > 0.0 && spread < 10.0);
+
+        // a fixed seed repeats the same samples
+        let mut first = VM::new();
+        let mut second = VM::new();
+        first.set_seed(7);
+        second.set_seed(7);
+        let program = "[rand_normal(), rand_normal(), rand_normal(5, 1)]";
+        assert_eq!(
+            first.run(&compile(program).unwrap()),
+            second.run(&compile(program).unwrap())
+        );
+        let samples = first.pop_value();
+        assert!(matches!(&samples, Some(StackItem::Array(x)) if x.len() == 3));
+        assert_eq!(samples, second.pop_value());
+
+        let builtins = super::builtins();
+        let rand_normal = builtins.functions.iter().find(|x| x.name == "rand_normal");
+        assert_eq!(Some(&vec![0, 2]), rand_normal.map(|x| &x.arities));
+        assert!(compile("rand_normal(1)").is_err());
+        assert_eq!(
+            "2 * rand_normal(1, 2)",
+            super::format("2*rand_normal(1,2)").unwrap()
+        );
+
+        // names merely starting with 'rand_normal' are still identifiers, so 'rand' stops at a
+        // boundary too
+        assert_eq!(
+            Some(1.0),
+            compute(&mut vm, "let rand_normal_x = 1; rand_normal_x")
+        );
+        assert_eq!(Some(2.0), compute(&mut vm, "let random = 2; random"));
+    }
+
+    #[test]
+    fn can_simulate_repeated_calls() {
+        let mut vm = VM::new();
+        vm.set_seed(3);
+        let mean = compute(&mut vm, "simulate(() => rand(), 1000)").unwrap();
+        assert!((mean - 0.5).abs() < 0.05, "{mean}");
+        let mean = compute(&mut vm, "simulate(() => rand_normal(4, 1), 2000)").unwrap();
+        assert!((mean - 4.0).abs() < 0.1, "{mean}");
+
+        assert_eq!(None, compute(&mut vm, "let count = 0"));
+        assert_eq!(
+            None,
+            compute(&mut vm, "let tally = () => count := count + 1")
+        );
+        assert_eq!(Some(5.5), compute(&mut vm, "simulate(tally, 10)"));
+        assert_eq!(Some(10.0), compute(&mut vm, "count"));
+
+        let err = |vm: &mut VM, program: &str| vm.run(&compile(program).unwrap()).unwrap_err();
+        assert!(err(&mut vm, "simulate(() => 1, 0)").contains("positive whole number"));
+        assert!(err(&mut vm, "simulate(() => 1, 2.5)").contains("positive whole number"));
+        assert!(err(&mut vm, "simulate(1, 10)").contains("expects a function"));
+        assert!(err(&mut vm, "simulate(() => [1, 2], 2)").contains("return a number"));
+        vm.set_max_simulate_runs(100);
+        assert!(err(&mut vm, "simulate(() => 1, 101)").contains("exceeds the limit of 100"));
+
+        // names merely starting with 'simulate' are still identifiers
+        assert_eq!(None, compute(&mut vm, "let simulated = 1"));
+        assert_eq!(Some(1.0), compute(&mut vm, "simulated"));
+    }
+
+    #[test]
+    fn can_normalize_palette_entries() {
+        use crate::palette::{normalize_expression, PaletteEntry};
+
+        let entry = PaletteEntry::new("Hypotenuse", "sqrt(a^2+b^2)").unwrap();
+        assert_eq!("sqrt(a^2 + b^2)", entry.expression);
+        assert_eq!(Ok("1 + 2".to_string()), normalize_expression(" 1+2 "));
+        assert_eq!(
+            Ok("8 - (4 - 2)".to_string()),
+            normalize_expression("8-(4-2)")
+        );
+        assert_eq!(Ok("(1 + 2)".to_string()), normalize_expression("(1+2)"));
+
+        // fragments are kept as typed as long as they tokenize
+        assert_eq!(Ok("2 *".to_string()), normalize_expression(" 2 * "));
+        assert!(PaletteEntry::new("Broken", "1 # 2").is_err());
+        assert!(PaletteEntry::new("Blank", "  ").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn can_round_trip_palette_entries() {
+        use crate::palette::{HistoryEntry, PaletteEntry};
+
+        let entry = PaletteEntry::new("Hypotenuse", "sqrt(a^2+b^2)").unwrap();
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(
+            r#"{"label":"Hypotenuse","data-expr":"sqrt(a^2 + b^2)"}"#,
+            json
+        );
+        assert_eq!(entry, serde_json::from_str(&json).unwrap());
+
+        let entry = HistoryEntry::new(" 1+2 ", "3").unwrap();
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(
+            serde_json::json!({ "data-expr": "1 + 2", "value": "3" }),
+            json
+        );
+        assert_eq!(entry, serde_json::from_value(json).unwrap());
+    }
+
+    #[test]
+    fn can_canonicalize_expressions() {
+        let key = super::canonical("1+2").unwrap();
+        assert_eq!(Ok(key.clone()), super::canonical("1 + 2"));
+        assert_eq!(Ok(key.clone()), super::canonical("1  +  2"));
+        assert_eq!(Ok(key.clone()), super::canonical("(1 + 2)"));
+        assert_ne!(Ok(key), super::canonical("2 + 1"));
+        assert_eq!(
+            super::canonical("let f = (x) => x*2"),
+            super::canonical("let  f=(x)=>x * 2")
+        );
+        assert!(super::canonical("1 +").is_err());
+
+        // parentheses that change the grouping are kept
+        assert_ne!(super::canonical("8-(4-2)"), super::canonical("8-4-2"));
+        assert_ne!(super::canonical("8/(4/2)"), super::canonical("8/4/2"));
+        assert_ne!(super::canonical("8-(4+2)"), super::canonical("8-4+2"));
+        assert_eq!(Ok("8/(4*2)".to_string()), super::minify("8/(4*2)"));
+        assert_eq!(Ok("8 % (5 % 3)".to_string()), super::format("8%(5%3)"));
+    }
+
+    #[test]
+    fn can_trace_evaluation() {
+        use crate::vm::{StackItem, TraceStep};
+
+        let step = |instruction, top| TraceStep {
+            instruction,
+            top: Some(StackItem::Number(top)),
+        };
+        let mut vm = VM::new();
+        let (result, steps) = vm.run_traced(&compile("1 + 2 * 3").unwrap());
+        assert_eq!(Ok(()), result);
+        assert_eq!(
+            vec![
+                step(Instruction::Push(1.0), 1.0),
+                step(Instruction::Push(2.0), 2.0),
+                step(Instruction::Push(3.0), 3.0),
+                step(Instruction::Mul, 6.0),
+                step(Instruction::Add, 7.0),
+            ],
+            steps
+        );
+        assert_eq!(Some(7.0), vm.pop_result());
+
+        // steps up to a failure are kept
+        vm.set_strict_math(true);
+        let (result, steps) = vm.run_traced(&compile("1 + 1 / 0").unwrap());
+        assert!(result.is_err());
+        assert_eq!(3, steps.len());
+        vm.clear_stack();
+        assert_eq!((Ok(()), vec![]), vm.run_traced(&[]));
+    }
+
+    #[test]
+    fn can_compute_sqrt() {
+        let mut vm = VM::new();
+        assert_eq!(10.0, compute(&mut vm, "sqrt(100)").unwrap().round());
+        assert_eq!(10.0, compute(&mut vm, "sqrt(100)").unwrap());
+        assert_eq!(2f64.sqrt(), compute(&mut vm, "sqrt(2)").unwrap());
+        assert!(compute(&mut vm, "sqrt(0 - 1)").unwrap().is_nan());
+    }
+
+    #[test]
+    fn can_compute_strict_math() {
+        let mut vm = VM::new();
+        assert!(compute(&mut vm, "1 / 0").unwrap().is_infinite());
+
+        vm.set_strict_math(true);
+        assert!(vm.run(&compile("sqrt(0 - 1)").unwrap()).is_err());
+        assert!(vm.run(&compile("1 / 0").unwrap()).is_err());
+        assert!(vm.run(&compile("1 mod 0").unwrap()).is_err());
+        assert_eq!(2.0, compute(&mut vm, "sqrt(4)").unwrap());
+    }
+
+    #[test]
+    fn can_compute_missing_variable_policies() {
+        let mut vm = VM::new();
+        assert_eq!(1.0, compute(&mut vm, "missing + 1").unwrap());
+
+        vm.set_missing_default(vm::MissingPolicy::Nan);
+        assert!(compute(&mut vm, "missing + 1").unwrap().is_nan());
+        assert_eq!(2.0, compute(&mut vm, "let x = 1; x + 1").unwrap());
+
+        vm.set_missing_default(vm::MissingPolicy::Error);
+        let err = vm.run(&compile("missing + 1").unwrap()).unwrap_err();
+        assert_eq!("missing variable 'missing'", err);
+        assert_eq!(2.0, compute(&mut vm, "toRadians(0) + 2").unwrap());
+
+        vm.set_missing_default(vm::MissingPolicy::Zero);
+        assert_eq!(0.0, compute(&mut vm, "other").unwrap());
+    }
+
+    #[test]
+    fn can_resolve_identifier_uses() {
+        use crate::resolver::{resolve, IdentKind, IdentUse};
+
+        fn uses(input: &str) -> Vec<(String, IdentKind)> {
+            let expression = parse(input).unwrap();
+            let uses = resolve(&expression).into_iter();
+            uses.map(|IdentUse { name, kind }| (name, kind)).collect()
+        }
+
+        let expected = vec![
+            ("f".to_string(), IdentKind::Call),
+            ("x".to_string(), IdentKind::Value),
+            ("y".to_string(), IdentKind::Value),
+        ];
+        assert_eq!(expected, uses("f(x) + y"));
+
+        let expected = vec![
+            ("a".to_string(), IdentKind::Value),
+            ("g".to_string(), IdentKind::FunctionRef),
+            ("g".to_string(), IdentKind::Call),
+        ];
+        assert_eq!(expected, uses("let g = (n) => 2; map(a, g); g(1)"));
+        assert!(uses("let z = 1; (p) => 2").is_empty());
+    }
+
+    #[test]
+    fn can_define_function() {
+        let mut vm = VM::new();
+        vm.define_function("sq", &["x"], "x*x").unwrap();
+        assert_eq!(25.0, compute(&mut vm, "sq(5)").unwrap());
+        vm.define_function("hyp", &["a", "b"], "sqrt(sq(a) + sq(b))")
+            .unwrap();
+        assert_eq!(5.0, compute(&mut vm, "hyp(3, 4)").unwrap());
+        vm.define_function("block", &[], "{ let y = 2; y + 1 }")
+            .unwrap();
+        assert_eq!(3.0, compute(&mut vm, "block()").unwrap());
+        assert_eq!("x * x", vm.get_function("sq").unwrap().body);
+
+        assert!(vm.define_function("bad name", &["x"], "x").is_err());
+        assert!(vm.define_function("f", &["1"], "x").is_err());
+        assert!(vm.define_function("f", &["x"], "x; 2").is_err());
+        assert!(vm.define_function("f", &["x"], "x +").is_err());
+        assert_eq!(0, vm.stack_depth());
+    }
+
+    #[test]
+    fn can_compute_newline_separated_statements() {
+        let options = compiler::CompileOptions {
+            newline_separators: true,
+            ..Default::default()
+        };
+        let mut vm = VM::new();
+        let mut run = |input| {
+            vm.run(&compile_with(input, options).unwrap()).unwrap();
+            let result = vm.pop_result();
+            vm.clear_stack();
+            result
+        };
+        assert_eq!(Some(2.0), run("let x = 1\nx + 1"));
+        assert_eq!(Some(3.0), run("\n  let y = 1 +\n    2\n\n  y\n"));
+        assert_eq!(
+            Some(4.0),
+            run("let f = (a) => {\n  let b = a * 2\n  b\n}\nf(2)")
+        );
+        assert_eq!(Some(5.0), run("if (0) {\n  1\n}\nelse {\n  5\n}"));
+        assert_eq!(Some(6.0), run("let z = [\n  1,\n  2\n]\nz[0] + z[1] + 3"));
+        assert_eq!(Some(1.0), run("let w = 2; w\n- 1"));
+        assert_eq!(Some(-1.0), run("let v = 2; v\n-1"));
+
+        // off by default, so the line break is just whitespace (example: 'let q = 2 (3 + 1)')
+        assert_eq!(None, compute(&mut vm, "let q = 2\n(3 + 1)"));
+        assert_eq!(8.0, compute(&mut vm, "q").unwrap());
+        assert_eq!(2.0, compute(&mut vm, "1 +\n1").unwrap());
+    }
+
+    #[test]
+    fn can_compute_integer_literals_exactly() {
+        use crate::vm::StackItem;
+
+        let options = compiler::CompileOptions {
+            integer_literals: true,
+            ..Default::default()
+        };
+        let mut vm = VM::new();
+        let mut run = |input| {
+            vm.run(&compile_with(input, options).unwrap()).unwrap();
+            vm.pop_value()
+        };
+        let exact = Some(StackItem::Int(9007199254740994));
+        assert_eq!(exact, run("9007199254740993 + 1"));
+        assert_eq!(Some(StackItem::Int(6)), run("2 * 3"));
+        assert_eq!(Some(StackItem::Int(3)), run("6 / 2"));
+        assert_eq!(Some(StackItem::Number(3.5)), run("7 / 2"));
+        assert_eq!(Some(StackItem::Number(2.5)), run("2 + 0.5"));
+        assert_eq!(
+            Some(StackItem::Int(1)),
+            run("9007199254740993 > 9007199254740992")
+        );
+        assert_eq!(Some(StackItem::Int(1024)), run("2^10"));
+        assert_eq!(Some(StackItem::Number(0.5)), run("2^(0 - 1)"));
+        assert_eq!(Some(StackItem::Int(-48)), run("-50 + 2"));
+        assert_eq!(
+            Some(StackItem::Number(2.0_f64.powi(64))),
+            run("2^32 * 2.0^32")
+        );
+        assert_eq!(Some(StackItem::Int(-1)), run("let f = (x) => x - 1; f(0)"));
+
+        assert_eq!(Some(StackItem::Number(9007199254740992.0)), {
+            vm.run(&compile("9007199254740993 + 1").unwrap()).unwrap();
+            vm.pop_value()
+        });
+    }
+
+    #[test]
+    fn can_handle_integer_overflow() {
+        use crate::vm::{IntOverflow, StackItem};
+
+        let options = compiler::CompileOptions {
+            integer_literals: true,
+            ..Default::default()
+        };
+        let max_plus_one = compile_with("9223372036854775807 + 1", options).unwrap();
+        let min_times_two = compile_with("(0 - 9223372036854775807 - 1) * 2", options).unwrap();
+        let mut vm = VM::new();
+
+        let err = vm.run(&max_plus_one).unwrap_err();
+        assert!(err.contains("integer overflow"), "{err}");
+        assert!(vm.run(&compile_with("2^63", options).unwrap()).is_err());
+
+        vm.set_int_overflow(IntOverflow::Wrap);
+        vm.run(&max_plus_one).unwrap();
+        assert_eq!(Some(StackItem::Int(i64::MIN)), vm.pop_value());
+        vm.run(&min_times_two).unwrap();
+        assert_eq!(Some(StackItem::Int(0)), vm.pop_value());
+
+        vm.set_int_overflow(IntOverflow::Saturate);
+        vm.run(&max_plus_one).unwrap();
+        assert_eq!(Some(StackItem::Int(i64::MAX)), vm.pop_value());
+        vm.run(&min_times_two).unwrap();
+        assert_eq!(Some(StackItem::Int(i64::MIN)), vm.pop_value());
+        vm.run(&compile_with("2^63", options).unwrap()).unwrap();
+        assert_eq!(Some(StackItem::Int(i64::MAX)), vm.pop_value());
+        // only overflow is affected, not results that were never integers
+        vm.run(&compile_with("2^(0 - 1)", options).unwrap())
+            .unwrap();
+        assert_eq!(Some(StackItem::Number(0.5)), vm.pop_value());
+    }
+
+    #[test]
+    fn can_compute_tuples() {
+        use crate::vm::StackItem;
+
+        let mut vm = VM::new();
+        assert_eq!(3.0, compute(&mut vm, "let a, b = (1, 2); a + b").unwrap());
+
+        compute(&mut vm, "let f = (x) => (x, x * x)");
+        vm.run(&compile("f(3)").unwrap()).unwrap();
+        let expected = StackItem::Tuple(vec![StackItem::Number(3.0), StackItem::Number(9.0)]);
+        assert_eq!(Some(expected), vm.pop_value());
+        assert_eq!(12.0, compute(&mut vm, "let x, y = f(3); x + y").unwrap());
+        assert_eq!(5.0, compute(&mut vm, "(2 + 3)").unwrap());
+
+        let err = vm
+            .run(&compile("let p, q = (1, 2, 3)").unwrap())
+            .unwrap_err();
+        assert_eq!("cannot destructure a 3-tuple into 2 variables", err);
+        assert!(vm.run(&compile("let p, q = 1").unwrap()).is_err());
+
+        let input = "let f = (x) => (x, x * x); let a, b = f(3); (a,)";
+        assert_eq!(input, super::format(input).unwrap());
+        assert_eq!("let a,b=(1,2)", super::minify("let a, b = (1, 2)").unwrap());
+    }
+
+    #[test]
+    fn can_compute_log2() {
+        let mut vm = VM::new();
+        assert_eq!(10.0, compute(&mut vm, "log2(1024)").unwrap());
+        assert_eq!(0.0, compute(&mut vm, "log2(1)").unwrap());
+        assert_eq!(2.0, compute(&mut vm, "log(100)").unwrap());
+        assert_eq!(
+            Ok(vec![
+                Token::Log2,
+                Token::OpenParen,
+                Token::LiteralNum(8.0),
+                Token::CloseParen
+            ]),
+            tokenize("log2(8)")
+        );
+        assert_eq!("log2(x) + log(x)", super::format("log2(x)+log(x)").unwrap());
+
+        // names merely starting with 'log2' are still identifiers, so 'log' stops at a boundary too
+        assert_eq!(Some(1.0), compute(&mut vm, "let log2x = 1; log2x"));
+        assert_eq!(Some(2.0), compute(&mut vm, "let logs = 2; logs"));
+    }
+
+    #[test]
+    fn can_compute_reciprocal_trig() {
+        let mut vm = VM::new();
+        assert_eq!(1.0, compute(&mut vm, "sec(0)").unwrap());
+        assert_eq!(1.0, compute(&mut vm, "csc(90)").unwrap());
+        assert_eq!(1.0, compute(&mut vm, "cot(45)").unwrap().to_fixed(6));
+        assert_eq!(2.0, compute(&mut vm, "sec(60)").unwrap().to_fixed(6));
+        assert_eq!(f64::INFINITY, compute(&mut vm, "csc(0)").unwrap());
+        assert_eq!(f64::INFINITY, compute(&mut vm, "sec(270)").unwrap());
+        assert_eq!(f64::INFINITY, compute(&mut vm, "cot(180)").unwrap());
+        assert_eq!(
+            "cot(x) + sec(x) + csc(x)",
+            super::format("cot(x)+sec(x)+csc(x)").unwrap()
+        );
+
+        vm.set_strict_math(true);
+        let err = vm.run(&compile("csc(0)").unwrap()).unwrap_err();
+        assert_eq!("csc is undefined at 0", err);
+        assert!(vm.run(&compile("sec(90)").unwrap()).is_err());
+        assert_eq!(1.0, compute(&mut vm, "csc(90)").unwrap());
+
+        // names merely starting with 'csc', 'sec' or 'cot' are still identifiers
+        assert_eq!(Some(5.0), compute(&mut vm, "let seconds = 5; seconds"));
+        assert_eq!(Some(2.0), compute(&mut vm, "let cotton = 2; cotton"));
+        assert_eq!(Some(3.0), compute(&mut vm, "let cscale = 3; cscale"));
+    }
+
+    #[test]
+    fn can_compute_factorial_past_f64_range() {
+        let mut vm = VM::new();
+        let largest = compute(&mut vm, "170!").unwrap();
+        assert!(largest.is_finite() && largest > 7.25e306);
+        assert_eq!(f64::INFINITY, compute(&mut vm, "171!").unwrap());
+        assert_eq!(f64::INFINITY, compute(&mut vm, "gamma(172)").unwrap());
+        for (n, expected) in [(150.0, compute(&mut vm, "149!")), (171.0, Some(largest))] {
+            let gamma = compute(&mut vm, &format!("gamma({n})")).unwrap();
+            let expected = expected.unwrap();
+            assert!(
+                ((gamma - expected) / expected).abs() < 1e-10,
+                "gamma({n}) = {gamma}"
+            );
+        }
+
+        vm.set_strict_math(true);
+        assert_eq!(largest, compute(&mut vm, "170!").unwrap());
+        assert!(compute(&mut vm, "gamma(171)").unwrap().is_finite());
+        assert!(vm.run(&compile("171!").unwrap()).is_err());
+        assert!(vm.run(&compile("gamma(172)").unwrap()).is_err());
+    }
+
+    #[test]
+    fn can_compile_pow_of_func_result() {
+        let mut instructions = instr_iter("sqrt(9)^2").into_iter();
+
+        assert_eq!(Some(Instruction::Push(9.0)), instructions.next());
+        assert_eq!(Some(Instruction::Sqrt), instructions.next());
+        assert_eq!(Some(Instruction::Push(2.0)), instructions.next());
+        assert_eq!(Some(Instruction::Pow), instructions.next());
+        assert_eq!(None, instructions.next());
+    }
+
+    #[test]
+    fn can_compute_pow_of_func_result() {
+        let mut vm = VM::new();
+        assert_eq!(9.0, compute(&mut vm, "sqrt(9)^2").unwrap().to_fixed(6));
+        assert_eq!(4.0, compute(&mut vm, "sqrt(16)").unwrap().to_fixed(6));
+        assert_eq!(10.0, compute(&mut vm, "sqrt(9)^2 + 1").unwrap().to_fixed(6));
+        assert_eq!(1.0, compute(&mut vm, "sin(90)^2").unwrap().to_fixed(6));
+
+        assert_eq!(None, compute(&mut vm, "let x = 3"));
+        assert_eq!(18.0, compute(&mut vm, "2x^2").unwrap().round());
+        assert_eq!(9.0, compute(&mut vm, "sqrt(x^2)^2").unwrap().to_fixed(6));
+    }
+
+    #[test]
+    fn can_compile_multiple() {
+        let mut instructions = instr_iter("3 - sin(90)").into_iter();
+
+        assert_eq!(Some(Instruction::Push(3.0)), instructions.next());
+        assert_eq!(Some(Instruction::Push(90.0)), instructions.next());
+        assert_eq!(Some(Instruction::Sine), instructions.next());
+        assert_eq!(Some(Instruction::Sub), instructions.next());
+        assert_eq!(None, instructions.next());
+    }
+
+    #[test]
+    fn can_compute_multiple() {
+        let mut vm = VM::new();
+        assert_eq!(2.0, compute(&mut vm, "3 - sin(90)").unwrap().round());
+    }
+
+    #[test]
+    fn can_compile_with_basic_parens() {
+        let mut instructions = instr_iter("2 + (20 - 10)").into_iter();
+
+        assert_eq!(Some(Instruction::Push(2.0)), instructions.next());
+        assert_eq!(Some(Instruction::Push(20.0)), instructions.next());
+        assert_eq!(Some(Instruction::Push(10.0)), instructions.next());
+        assert_eq!(Some(Instruction::Sub), instructions.next());
+        assert_eq!(Some(Instruction::Add), instructions.next());
+        assert_eq!(None, instructions.next());
+    }
+
+    #[test]
+    fn can_compute_with_basic_parens() {
+        let mut vm = VM::new();
+        assert_eq!(20.0, compute(&mut vm, "2 * (20 - 10)").unwrap().round());
+        assert_eq!(30.0, compute(&mut vm, "(2 * 20) - 10").unwrap().round());
+    }
+
+    #[test]
+    fn can_compute_with_implicit_multiplication() {
+        let mut vm = VM::new();
+        assert_eq!(20.0, compute(&mut vm, "2(20 - 10)").unwrap().round());
+        assert_eq!(None, compute(&mut vm, "let x = 3"));
+        assert_eq!(16.0, compute(&mut vm, "(x)(x) + 2x + 1").unwrap().round());
+        assert_eq!(Some(2.0), compute(&mut vm, "2sin(90)"));
+        assert_eq!(Some(6.0), compute(&mut vm, "3sqrt(4)"));
+        assert_eq!(Some(7.0), compute(&mut vm, "1 + 3sqrt(4)"));
+        assert_eq!(Some(10.0), compute(&mut vm, "2max(1, 5)"));
+
+        // every VM's generator starts from the same seed, so both draw the same number
+        let explicit = compute(&mut VM::new(), "2 * rand()").unwrap();
+        assert_eq!(Some(explicit), compute(&mut VM::new(), "2rand()"));
+        assert_eq!(compile("3 * rand() + 1"), compile("3rand() + 1"));
+        // like '3x', only a literal may be implicitly multiplied by a call
+        assert!(compile("rand()rand()").is_err());
+    }
+
+    #[test]
+    fn can_disable_implicit_multiplication() {
+        let options = compiler::CompileOptions {
+            implicit_multiplication: false,
+            ..Default::default()
+        };
+        assert!(compile_with("let x = 3; 2x", options).is_err());
+        assert!(compile_with("(1)(2)", options).is_err());
+
+        let mut vm = VM::new();
+        let program = compile_with("let x = 3; 2*x", options).unwrap();
+        vm.run(&program).unwrap();
+        assert_eq!(Some(6.0), vm.pop_result());
+    }
+
+    #[test]
+    fn can_compute_with_precedence() {
+        let mut vm = VM::new();
+        assert_eq!(11.0, compute(&mut vm, "2 * 3 + 5").unwrap().round());
+        assert_eq!(17.0, compute(&mut vm, "2 + 3 * 5").unwrap().round());
+    }
+
+    #[test]
+    fn can_compute_variables() {
+        let mut vm = VM::new();
+        compute(&mut vm, "let x = 1 + 2");
+        assert_eq!(5.0, compute(&mut vm, "x + 2").unwrap().round());
+    }
+
+    #[test]
+    fn can_compute_blocks() {
+        let mut vm = VM::new();
+        compute(&mut vm, "let x = { let y = 1 + 2; y + 3 }");
+        assert_eq!(6.0, compute(&mut vm, "x").unwrap().round());
+
+        vm.run(&compile("{ 1; 2 }").unwrap()).unwrap();
+        assert_eq!(1, vm.stack_depth());
+        assert_eq!(Some(2.0), vm.pop_result());
+        vm.run(&compile("{ 1; let x = 2 }").unwrap()).unwrap();
+        assert_eq!(0, vm.stack_depth());
+        vm.run(&compile("3; { 1; let x = 2 }").unwrap()).unwrap();
+        assert_eq!(Some(3.0), vm.pop_result());
+        assert_eq!(
+            None,
+            compute(&mut vm, "let f = () => { 1; let y = 2 }; f()")
+        );
+        assert_eq!(
+            5.0,
+            compute(&mut vm, "let g = () => { 1; 5 }; 1 + g()").unwrap() - 1.0
+        );
+
+        let expected = [
+            Instruction::Enter,
+            Instruction::Push(1.0),
+            Instruction::Discard,
+            Instruction::Push(2.0),
+            Instruction::Leave,
+        ];
+        assert_eq!(expected.as_slice(), instr_iter("{ 1; 2 }"));
+    }
+
+    #[test]
+    fn can_compute_top_level_statements() {
+        let mut instructions = instr_iter("let x = 1; x + 1").into_iter();
+
+        assert_eq!(Some(Instruction::Push(1.0)), instructions.next());
+        assert_eq!(
+            Some(Instruction::Assign(String::from("x"))),
+            instructions.next()
+        );
+        assert_eq!(
+            Some(Instruction::LoadLocal(String::from("x"))),
+            instructions.next()
+        );
+        assert_eq!(Some(Instruction::Push(1.0)), instructions.next());
+        assert_eq!(Some(Instruction::Add), instructions.next());
+        assert_eq!(None, instructions.next());
+
+        let mut vm = VM::new();
+        assert_eq!(2.0, compute(&mut vm, "let x = 1; x + 1").unwrap().round());
+        assert_eq!(None, compute(&mut vm, "let y = 2; let z = x + y"));
+        assert_eq!(3.0, compute(&mut vm, "z").unwrap().round());
+
+        let formatted = super::format("let x=1 ;x+1").unwrap();
+        assert_eq!("let x = 1; x + 1", formatted);
+        let minified = super::minify("let x = 1; x + 1").unwrap();
+        assert_eq!("let x=1;x+1", minified);
+    }
+
+    #[test]
+    fn can_compute_block_fn() {
+        let mut vm = VM::new();
+        compute(
+            &mut vm,
+            "let calc = (x) => { let y = 2(x + 1); y^2 + y + 3 }",
+        );
+        assert_eq!(45.0, compute(&mut vm, "calc(2)").unwrap().round());
+
+        compute(&mut vm, "let y = 15");
+        compute(&mut vm, "let calc2 = ( x ) => { let y = y * x }");
+        assert_eq!(None, compute(&mut vm, "calc2(3)"));
+        assert_eq!(45.0, compute(&mut vm, "y").unwrap().round());
+
+        compute(&mut vm, "let calc = (x, y) => { x^2 + y^2 }");
+        assert_eq!(13.0, compute(&mut vm, "calc(2, 3)").unwrap().round());
+
+        compute(&mut vm, "let calc = () => {}");
+        compute(&mut vm, "calc()");
+        assert!(vm.pop_result().is_none());
+
+        compute(
+            &mut vm,
+            r#"
+            let repeat = (x, n) => {
+                let c = floor(log(x)) + 1;
+                let y = 0;
+                let loop = (i, fn) => {
+                    if (i > 0) {
+                        fn(i - 1);
+                        loop(i - 1, fn)
+                    }
+                };
+                loop(n, (i) => {
+                    let y = y + (x * 10^(i * c))
+                });
+                y
+            }"#,
+        );
+        assert_eq!(98989898.0, compute(&mut vm, "repeat(98, 4)").unwrap().round());
+    }
+
+    #[test]
+    fn can_pretty_print() {
+        let formatted = super::format("let calc= ( x, ) =>sin (90)").unwrap();
+        assert_eq!("let calc = (x) => sin(90)", formatted);
+
+        let formatted = super::format("example(1-1)").unwrap();
+        assert_eq!("example(1 - 1)", formatted);
+
+        let formatted = super::format("(8 + 9 + 9)*2").unwrap();
+        assert_eq!("(8 + 9 + 9) * 2", formatted);
+
+        let minified = super::minify("let calc= ( x, ) =>sin (90)").unwrap();
+        assert_eq!("let calc=(x)=>sin(90)", minified);
+
+        let minified = super::minify("example(1 - 1)").unwrap();
+        assert_eq!("example(1-1)", minified);
+
+        let minified = super::minify("(8 + 9 + 9)*2").unwrap();
+        assert_eq!("(8+9+9)*2", minified);
+
+        let indented = super::format_pretty(
+            "let fib = (x) => { if (x < 2) { 1 } else { fib(x - 2) + fib(x - 1) }",
+        )
+        .unwrap();
+        let expected = r#"
+let fib = (x) => {
+    if (x < 2) {
+        1
+    } else {
+        fib(x - 2) + fib(x - 1)
+    }
+}"#;
+        assert_eq!(expected.trim(), indented.trim());
+    }
+
+    #[test]
+    fn can_pretty_print_large_fns() {
+        let indented = super::format_pretty(
             "let repeat = (x, n) => { let c = floor(log(x)) + 1; let y = 0; let loop = (i, fn) => { if (i > 0) { fn(i-1) } }; loop(n, (i) => { let y = y + x * 10^(i*c); }); y }",
         )
         .unwrap();
@@ -546,6 +2951,64 @@ let repeat = (x, n) => {
         assert_eq!(expected.trim(), indented.trim());
     }
 
+    #[test]
+    fn can_reject_deeply_nested_expressions() {
+        let nested = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+        let err = compile(&nested).unwrap_err();
+        assert!(err.contains("expression too deeply nested"), "{err}");
+
+        let nested = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        let mut vm = VM::new();
+        assert_eq!(1.0, compute(&mut vm, &nested).unwrap());
+
+        let tokens: Result<Vec<_>, _> = lexer::tokenize("((1))".into()).collect();
+        let tokens = tokens.unwrap();
+        let mut compiler = Compiler::new(&tokens);
+        compiler.set_max_depth(2);
+        assert!(compiler.compile().is_err());
+    }
+
+    #[test]
+    fn can_pretty_print_mod_keyword() {
+        let options = pretty::PrettyOptions {
+            prefer_mod_keyword: true,
+            ..Default::default()
+        };
+        let spaced = pretty::PrettyFormat::Spaced;
+        let minified = pretty::PrettyFormat::Minified;
+
+        assert_eq!("10 % 3", super::format("10 mod 3").unwrap());
+        assert_eq!(
+            "10 mod 3",
+            format_with("10 mod 3", spaced, options).unwrap()
+        );
+        assert_eq!("10 mod 3", format_with("10 % 3", spaced, options).unwrap());
+        assert_eq!(
+            "10 mod 3",
+            format_with("10 mod 3", minified, options).unwrap()
+        );
+        assert_eq!(
+            "(x-1) mod 3",
+            format_with("( x - 1 ) mod 3", minified, options).unwrap()
+        );
+
+        let mut vm = VM::new();
+        let minified = format_with("10 mod 3", minified, options).unwrap();
+        assert_eq!(1.0, compute(&mut vm, &minified).unwrap());
+    }
+
+    #[test]
+    fn can_handle_empty_input() {
+        assert_eq!(Ok(vec![]), compile(""));
+        assert_eq!(Ok(vec![]), compile("   "));
+        assert_eq!(Ok(String::new()), super::format(" \t "));
+
+        let mut vm = VM::new();
+        assert_eq!(None, super::compute(&mut vm, ""));
+        assert_eq!(None, super::compute(&mut vm, "   "));
+        assert_eq!(None, super::compute(&mut vm, "\n"));
+    }
+
     #[test]
     fn can_parse() {
         let source = parser::Bite::new("x + y = z");
@@ -578,6 +3041,9 @@ let repeat = (x, n) => {
                 panic!("{}", msg);
             }
         };
+        if program.is_empty() {
+            return None;
+        }
 
         match vm.run(&program) {
             Ok(_) => {}
@@ -586,7 +3052,9 @@ let repeat = (x, n) => {
             }
         }
 
-        vm.pop_result()
+        let result = vm.pop_result();
+        vm.clear_stack();
+        result
     }
 
     fn instr_iter(input: &str) -> Vec<Instruction> {
@@ -1,20 +1,399 @@
+use std::process::ExitCode;
+
 use xpress_calc::vm::VM;
 
-fn main() {
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum OutputMode {
+    #[default]
+    Result,
+    Ast,
+    Bytecode,
+    Format,
+}
+
+// how the REPL renders integer results, switched with ':base'
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum NumberBase {
+    #[default]
+    Dec,
+    Hex,
+    Bin,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    mode: OutputMode,
+    expression: Option<String>,
+    file: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}");
+            eprintln!(
+                "usage: xpress-calc [--ast | --bytecode | --format] [--file path] [[--eval] expression]"
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
     let mut vm = VM::new();
+    if let Some(path) = &args.file {
+        if let Err(err) = load_file(&mut vm, path) {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(expression) = args.expression {
+        return match evaluate(&mut vm, &expression, args.mode, NumberBase::Dec) {
+            Ok(output) => {
+                println!("{output}");
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let mut history = History::default();
+    let mut base = NumberBase::default();
     loop {
         print!("Enter expression (example: '5 + 2'): ");
-        let expression = read_line();
-        match xpress_calc::compute(&mut vm, &expression) {
-            Some(result) => println!("{result}"),
-            None => println!("<undefined>"),
+        let Some(line) = read_line() else {
+            return ExitCode::SUCCESS;
+        };
+        let expression = match history.resolve(&line) {
+            Ok(ReplInput::ShowHistory) => {
+                print!("{}", history.list());
+                continue;
+            }
+            Ok(ReplInput::SetBase(next_base)) => {
+                base = next_base;
+                continue;
+            }
+            Ok(ReplInput::Expression(expression)) => expression,
+            Err(err) => {
+                eprintln!("{err}");
+                continue;
+            }
+        };
+        history.push(&expression);
+        match evaluate(&mut vm, &expression, args.mode, base) {
+            Ok(output) => println!("{output}"),
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ReplInput {
+    ShowHistory,
+    SetBase(NumberBase),
+    Expression(String),
+}
+
+#[derive(Debug, Default)]
+struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    // skips repeats of the last entry, including ones that only differ in spacing (example: '1+2'
+    // after '1 + 2'), though entries are kept as typed
+    fn push(&mut self, expression: &str) {
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return;
+        }
+        if let Some(last) = self.entries.last() {
+            let key = |x: &str| xpress_calc::canonical(x).unwrap_or_else(|_| x.to_string());
+            if key(last) == key(expression) {
+                return;
+            }
+        }
+        self.entries.push(expression.to_string());
+    }
+
+    // entries are numbered from 1, matching the ':history' listing
+    fn get(&self, n: usize) -> Option<&str> {
+        self.entries.get(n.checked_sub(1)?).map(String::as_str)
+    }
+
+    fn list(&self) -> String {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| format!("{}: {entry}\n", idx + 1))
+            .collect()
+    }
+
+    // handles ':history', ':base' and ':N' commands, otherwise passing the line through as an
+    // expression
+    fn resolve(&self, line: &str) -> Result<ReplInput, String> {
+        let Some(command) = line.trim().strip_prefix(':') else {
+            return Ok(ReplInput::Expression(line.to_string()));
+        };
+        if command == "history" {
+            return Ok(ReplInput::ShowHistory);
+        }
+        // split on whitespace so the name must stand alone (example: ':basehex' isn't ':base hex')
+        let mut words = command.split_whitespace();
+        if words.next() == Some("base") {
+            return match words.collect::<Vec<_>>().join(" ").as_str() {
+                "dec" => Ok(ReplInput::SetBase(NumberBase::Dec)),
+                "hex" => Ok(ReplInput::SetBase(NumberBase::Hex)),
+                "bin" => Ok(ReplInput::SetBase(NumberBase::Bin)),
+                base => Err(format!(
+                    "unknown base '{base}', expected 'dec', 'hex' or 'bin'"
+                )),
+            };
+        }
+        let n: usize = command
+            .parse()
+            .map_err(|_| format!("unknown command ':{command}'"))?;
+        let entry = self.get(n).ok_or_else(|| format!("no history entry {n}"))?;
+        Ok(ReplInput::Expression(entry.to_string()))
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ast" => parsed.mode = OutputMode::Ast,
+            "--bytecode" => parsed.mode = OutputMode::Bytecode,
+            "--format" => parsed.mode = OutputMode::Format,
+            "--file" => {
+                let path = args.next().ok_or("expected a path after '--file'")?;
+                parsed.file = Some(path);
+            }
+            "--eval" => {
+                let expression = args.next().ok_or("expected an expression after '--eval'")?;
+                set_expression(&mut parsed, expression)?;
+            }
+            flag if flag.starts_with("--") => Err(format!("unknown flag '{flag}'"))?,
+            _ => set_expression(&mut parsed, arg)?,
+        }
+    }
+    Ok(parsed)
+}
+
+fn set_expression(parsed: &mut Args, expression: String) -> Result<(), String> {
+    if parsed.expression.is_some() {
+        return Err(String::from("expected a single expression"));
+    }
+    parsed.expression = Some(expression);
+    Ok(())
+}
+
+fn load_file(vm: &mut VM, path: &str) -> Result<(), String> {
+    let script = std::fs::read_to_string(path)
+        .map_err(|err| format!("could not read file '{path}': {err}"))?;
+    xpress_calc::run_script(vm, &script).map_err(|err| format!("{path}: {err}"))?;
+    Ok(())
+}
+
+fn evaluate(
+    vm: &mut VM,
+    expression: &str,
+    mode: OutputMode,
+    base: NumberBase,
+) -> Result<String, String> {
+    match mode {
+        OutputMode::Result => {
+            let program = xpress_calc::compile_for(vm, expression)?;
+            let result = vm.run(&program);
+            for warning in vm.take_warnings() {
+                eprintln!("WARN: {warning}");
+            }
+            if let Err(err) = result {
+                vm.clear_stack();
+                return Err(err);
+            }
+            let result = vm.pop_result();
+            vm.clear_stack();
+            match result {
+                Some(result) => Ok(format_result(result, base)),
+                None => Ok(String::from("<undefined>")),
+            }
+        }
+        OutputMode::Ast => Ok(format!("{:#?}", xpress_calc::parse(expression)?)),
+        OutputMode::Bytecode => {
+            let program = xpress_calc::compile(expression)?;
+            let lines: Vec<_> = program.iter().map(|x| format!("{x:?}")).collect();
+            Ok(lines.join("\n"))
         }
+        OutputMode::Format => xpress_calc::format(expression),
     }
 }
 
-fn read_line() -> String {
+fn format_result(result: f64, base: NumberBase) -> String {
+    let is_integer = result.fract() == 0.0 && result.abs() < i64::MAX as f64;
+    let sign = if result < 0.0 { "-" } else { "" };
+    let magnitude = result.abs() as i64;
+    match base {
+        NumberBase::Dec => result.to_string(),
+        NumberBase::Hex if is_integer => format!("{sign}0x{magnitude:x}"),
+        NumberBase::Bin if is_integer => format!("{sign}0b{magnitude:b}"),
+        NumberBase::Hex | NumberBase::Bin => format!("{result} (not an integer, shown in decimal)"),
+    }
+}
+
+fn read_line() -> Option<String> {
     std::io::Write::flush(&mut std::io::stdout()).unwrap();
     let mut buffer = String::new();
-    std::io::stdin().read_line(&mut buffer).unwrap();
-    buffer.trim_end_matches('\n').to_string()
+    match std::io::stdin().read_line(&mut buffer).unwrap() {
+        0 => None,
+        _ => Some(buffer.trim_end_matches('\n').to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(input: &[&str]) -> Result<Args, String> {
+        parse_args(input.iter().map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn can_parse_args() {
+        assert_eq!(Ok(Args::default()), args(&[]));
+        let parsed = args(&["2 + 3"]).unwrap();
+        assert_eq!(OutputMode::Result, parsed.mode);
+        assert_eq!(Some("2 + 3"), parsed.expression.as_deref());
+        assert_eq!(OutputMode::Ast, args(&["--ast", "1"]).unwrap().mode);
+        assert_eq!(
+            OutputMode::Bytecode,
+            args(&["1", "--bytecode"]).unwrap().mode
+        );
+        assert_eq!(OutputMode::Format, args(&["--format"]).unwrap().mode);
+
+        let parsed = args(&["--file", "calc.xc", "--eval", "f(2)"]).unwrap();
+        assert_eq!(Some("calc.xc"), parsed.file.as_deref());
+        assert_eq!(Some("f(2)"), parsed.expression.as_deref());
+
+        assert!(args(&["--nope"]).is_err());
+        assert!(args(&["1", "2"]).is_err());
+        assert!(args(&["--eval", "1", "2"]).is_err());
+        assert!(args(&["--file"]).is_err());
+    }
+
+    #[test]
+    fn can_store_and_recall_history() {
+        let mut history = History::default();
+        history.push("1 + 1");
+        history.push("  ");
+        history.push("let x = 2");
+        history.push("let x = 2");
+        history.push("let x=2");
+        assert_eq!("1: 1 + 1\n2: let x = 2\n", history.list());
+        assert_eq!(Some("1 + 1"), history.get(1));
+        assert_eq!(None, history.get(0));
+        assert_eq!(None, history.get(3));
+
+        assert_eq!(Ok(ReplInput::ShowHistory), history.resolve(":history"));
+        assert_eq!(
+            Ok(ReplInput::Expression("let x = 2".to_string())),
+            history.resolve(":2")
+        );
+        assert_eq!(
+            Ok(ReplInput::Expression("x * 3".to_string())),
+            history.resolve("x * 3")
+        );
+        assert!(history.resolve(":9").is_err());
+        assert!(history.resolve(":nope").is_err());
+    }
+
+    #[test]
+    fn can_load_script_file() {
+        let path = std::env::temp_dir().join("xpress-calc-load-script-test.xc");
+        std::fs::write(&path, "let f = (x) => x + 1\nlet y = f(2)\n").unwrap();
+
+        let mut vm = VM::new();
+        load_file(&mut vm, path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Ok("3".to_string()),
+            evaluate(&mut vm, "y", OutputMode::Result, NumberBase::Dec)
+        );
+        assert_eq!(
+            Ok("11".to_string()),
+            evaluate(&mut vm, "f(10)", OutputMode::Result, NumberBase::Dec)
+        );
+
+        let err = load_file(&mut vm, "does-not-exist.xc").unwrap_err();
+        assert!(
+            err.starts_with("could not read file 'does-not-exist.xc'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn can_toggle_number_base() {
+        let history = History::default();
+        assert_eq!(
+            Ok(ReplInput::SetBase(NumberBase::Hex)),
+            history.resolve(":base hex")
+        );
+        assert_eq!(
+            Ok(ReplInput::SetBase(NumberBase::Bin)),
+            history.resolve(" :base  bin")
+        );
+        assert_eq!(
+            Ok(ReplInput::SetBase(NumberBase::Dec)),
+            history.resolve(":base dec")
+        );
+        assert!(history.resolve(":base oct").is_err());
+        assert!(history.resolve(":base").is_err());
+        assert!(history.resolve(":base hex bin").is_err());
+        assert_eq!(
+            Err("unknown command ':basehex'".to_string()),
+            history.resolve(":basehex")
+        );
+
+        assert_eq!("0xff", format_result(255.0, NumberBase::Hex));
+        assert_eq!("-0x10", format_result(-16.0, NumberBase::Hex));
+        assert_eq!("0b101", format_result(5.0, NumberBase::Bin));
+        assert_eq!("255", format_result(255.0, NumberBase::Dec));
+        assert_eq!(
+            "2.5 (not an integer, shown in decimal)",
+            format_result(2.5, NumberBase::Hex)
+        );
+
+        let mut vm = VM::new();
+        assert_eq!(
+            Ok("0x2a".to_string()),
+            evaluate(&mut vm, "6 * 7", OutputMode::Result, NumberBase::Hex)
+        );
+    }
+
+    #[test]
+    fn can_evaluate_with_mode() {
+        let mut vm = VM::new();
+        assert_eq!(
+            Ok("5".to_string()),
+            evaluate(&mut vm, "2 + 3", OutputMode::Result, NumberBase::Dec)
+        );
+        assert_eq!(
+            Ok("Push(2.0)\nPush(3.0)\nAdd".to_string()),
+            evaluate(&mut vm, "2 + 3", OutputMode::Bytecode, NumberBase::Dec)
+        );
+        assert_eq!(
+            Ok("2 + 3".to_string()),
+            evaluate(&mut vm, "2+3", OutputMode::Format, NumberBase::Dec)
+        );
+        let ast = evaluate(&mut vm, "2 + 3", OutputMode::Ast, NumberBase::Dec).unwrap();
+        assert!(ast.starts_with("BinaryOp("), "{ast}");
+
+        assert!(evaluate(&mut vm, "2 +", OutputMode::Result, NumberBase::Dec).is_err());
+        assert_eq!(
+            Ok("<undefined>".to_string()),
+            evaluate(&mut vm, "", OutputMode::Result, NumberBase::Dec)
+        );
+    }
 }
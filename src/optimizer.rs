@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use crate::{
+    compiler::{Func2Op, Func3Op, RecursiveExpression},
+    resolver,
+    vm::{Instruction, StackItem, VM},
+};
+
+// folds arithmetic on literals, and substitutes variables bound once to a literal into the
+// top-level statements that follow their 'let'. being conservative, a variable isn't propagated if
+// it is assigned anywhere else in the program (function bodies and parameters included), into
+// function bodies (which look variables up wherever they are called from) or past a call (which
+// could reassign it)
+pub fn optimize(expression: RecursiveExpression) -> RecursiveExpression {
+    let mut assignments = HashMap::new();
+    count_assignments(&expression, &mut assignments);
+    let mut vm = VM::new();
+    // folding must not hide an error from a program that runs in strict mode
+    vm.set_strict_math(true);
+    let mut folder = Folder {
+        vm,
+        assignments,
+        constants: HashMap::new(),
+    };
+    match expression {
+        RecursiveExpression::Sequence(statements) => RecursiveExpression::Sequence(
+            statements
+                .into_iter()
+                .map(|node| folder.statement(node))
+                .collect(),
+        ),
+        statement => folder.statement(statement),
+    }
+}
+
+struct Folder {
+    vm: VM,
+    assignments: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+}
+
+impl Folder {
+    fn statement(&mut self, statement: RecursiveExpression) -> RecursiveExpression {
+        if contains_calls(&statement) {
+            self.constants.clear();
+        }
+        let statement = self.fold(statement);
+        if let RecursiveExpression::AssignOp(ident, value)
+        | RecursiveExpression::ConstAssign(ident, value) = &statement
+        {
+            if let (RecursiveExpression::Literal(x), Some(1)) =
+                (value.as_ref(), self.assignments.get(ident))
+            {
+                self.constants.insert(ident.clone(), *x);
+            }
+        }
+        statement
+    }
+
+    fn fold(&mut self, expression: RecursiveExpression) -> RecursiveExpression {
+        match expression {
+            RecursiveExpression::Local(ident) => match self.constants.get(&ident) {
+                Some(x) => RecursiveExpression::Literal(*x),
+                None => RecursiveExpression::Local(ident),
+            },
+            RecursiveExpression::FuncDeclaration(params, body) => {
+                let constants = std::mem::take(&mut self.constants);
+                let body = self.fold(*body);
+                self.constants = constants;
+                RecursiveExpression::FuncDeclaration(params, Box::new(body))
+            }
+            RecursiveExpression::BinaryOp(lhs, op, rhs) => {
+                let lhs = self.fold(*lhs);
+                let rhs = self.fold(*rhs);
+                if let (RecursiveExpression::Literal(x), RecursiveExpression::Literal(y)) =
+                    (&lhs, &rhs)
+                {
+                    let program = [
+                        Instruction::Push(*x),
+                        Instruction::Push(*y),
+                        op.to_instruction(),
+                    ];
+                    if let Some(result) = self.evaluate(&program) {
+                        return RecursiveExpression::Literal(result);
+                    }
+                }
+                RecursiveExpression::BinaryOp(Box::new(lhs), op, Box::new(rhs))
+            }
+            expression => map_children(expression, &mut |node| self.fold(node)),
+        }
+    }
+
+    fn evaluate(&mut self, program: &[Instruction]) -> Option<f64> {
+        self.vm.clear_stack();
+        self.vm.run(program).ok()?;
+        match self.vm.pop_value()? {
+            StackItem::Number(x) if x.is_finite() => Some(x),
+            _ => None,
+        }
+    }
+}
+
+fn count_assignments(expression: &RecursiveExpression, assignments: &mut HashMap<String, usize>) {
+    let mut assign = |ident: &String| *assignments.entry(ident.clone()).or_default() += 1;
+    match expression {
+        RecursiveExpression::AssignOp(ident, _)
+        | RecursiveExpression::ConstAssign(ident, _)
+        | RecursiveExpression::AssignExpr(ident, _)
+        | RecursiveExpression::IndexAssign(ident, ..) => assign(ident),
+        RecursiveExpression::DestructureAssign(idents, _)
+        | RecursiveExpression::FuncDeclaration(idents, _) => idents.iter().for_each(assign),
+        _ => {}
+    }
+    resolver::children(expression)
+        .into_iter()
+        .for_each(|node| count_assignments(node, assignments));
+}
+
+fn contains_calls(expression: &RecursiveExpression) -> bool {
+    match expression {
+        RecursiveExpression::FuncLocal(..)
+        | RecursiveExpression::Call(..)
+        | RecursiveExpression::Func2(Func2Op::Map | Func2Op::Simulate, ..)
+        | RecursiveExpression::Func3(Func3Op::Reduce, ..) => true,
+        expression => resolver::children(expression)
+            .into_iter()
+            .any(contains_calls),
+    }
+}
+
+fn map_children(
+    expression: RecursiveExpression,
+    f: &mut impl FnMut(RecursiveExpression) -> RecursiveExpression,
+) -> RecursiveExpression {
+    match expression {
+        RecursiveExpression::Sequence(nodes) => RecursiveExpression::Sequence(map_all(nodes, f)),
+        RecursiveExpression::Block(nodes) => RecursiveExpression::Block(map_all(nodes, f)),
+        RecursiveExpression::Tuple(nodes) => RecursiveExpression::Tuple(map_all(nodes, f)),
+        RecursiveExpression::Array(nodes) => RecursiveExpression::Array(map_all(nodes, f)),
+        RecursiveExpression::FuncN(op, nodes) => RecursiveExpression::FuncN(op, map_all(nodes, f)),
+        RecursiveExpression::FuncLocal(ident, nodes) => {
+            RecursiveExpression::FuncLocal(ident, map_all(nodes, f))
+        }
+        RecursiveExpression::Call(target, nodes) => {
+            RecursiveExpression::Call(map_one(target, f), map_all(nodes, f))
+        }
+        expression @ (RecursiveExpression::Literal(_)
+        | RecursiveExpression::LiteralInt(_)
+        | RecursiveExpression::LiteralStr(_)
+        | RecursiveExpression::Local(_)
+        | RecursiveExpression::Func0(_)) => expression,
+        RecursiveExpression::Group(node) => RecursiveExpression::Group(map_one(node, f)),
+        RecursiveExpression::FuncDeclaration(params, node) => {
+            RecursiveExpression::FuncDeclaration(params, map_one(node, f))
+        }
+        RecursiveExpression::AssignOp(ident, node) => {
+            RecursiveExpression::AssignOp(ident, map_one(node, f))
+        }
+        RecursiveExpression::ConstAssign(ident, node) => {
+            RecursiveExpression::ConstAssign(ident, map_one(node, f))
+        }
+        RecursiveExpression::AssignExpr(ident, node) => {
+            RecursiveExpression::AssignExpr(ident, map_one(node, f))
+        }
+        RecursiveExpression::DestructureAssign(idents, node) => {
+            RecursiveExpression::DestructureAssign(idents, map_one(node, f))
+        }
+        RecursiveExpression::Not(node) => RecursiveExpression::Not(map_one(node, f)),
+        RecursiveExpression::Factorial(node) => RecursiveExpression::Factorial(map_one(node, f)),
+        RecursiveExpression::Func1(op, node) => RecursiveExpression::Func1(op, map_one(node, f)),
+        RecursiveExpression::Range(lhs, rhs, inclusive) => {
+            RecursiveExpression::Range(map_one(lhs, f), map_one(rhs, f), inclusive)
+        }
+        RecursiveExpression::If(lhs, rhs) => {
+            RecursiveExpression::If(map_one(lhs, f), map_one(rhs, f))
+        }
+        RecursiveExpression::IndexAssign(ident, lhs, rhs) => {
+            RecursiveExpression::IndexAssign(ident, map_one(lhs, f), map_one(rhs, f))
+        }
+        RecursiveExpression::BinaryOp(lhs, op, rhs) => {
+            RecursiveExpression::BinaryOp(map_one(lhs, f), op, map_one(rhs, f))
+        }
+        RecursiveExpression::Index(lhs, rhs) => {
+            RecursiveExpression::Index(map_one(lhs, f), map_one(rhs, f))
+        }
+        RecursiveExpression::Func2(op, lhs, rhs) => {
+            RecursiveExpression::Func2(op, map_one(lhs, f), map_one(rhs, f))
+        }
+        RecursiveExpression::IfElse(arg1, arg2, arg3) => {
+            RecursiveExpression::IfElse(map_one(arg1, f), map_one(arg2, f), map_one(arg3, f))
+        }
+        RecursiveExpression::Func3(op, arg1, arg2, arg3) => {
+            RecursiveExpression::Func3(op, map_one(arg1, f), map_one(arg2, f), map_one(arg3, f))
+        }
+    }
+}
+
+fn map_one(
+    node: Box<RecursiveExpression>,
+    f: &mut impl FnMut(RecursiveExpression) -> RecursiveExpression,
+) -> Box<RecursiveExpression> {
+    Box::new(f(*node))
+}
+
+fn map_all(
+    nodes: Vec<RecursiveExpression>,
+    f: &mut impl FnMut(RecursiveExpression) -> RecursiveExpression,
+) -> Vec<RecursiveExpression> {
+    nodes.into_iter().map(f).collect()
+}
@@ -0,0 +1,58 @@
+// typed forms of the expression palette and history entries the web app stores as JSON
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{pretty, CalcError};
+
+/// A saved expression offered in the command palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PaletteEntry {
+    pub label: String,
+    #[cfg_attr(feature = "serde", serde(rename = "data-expr"))]
+    pub expression: String,
+}
+
+/// An expression that was evaluated, with the result shown for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HistoryEntry {
+    #[cfg_attr(feature = "serde", serde(rename = "data-expr"))]
+    pub expression: String,
+    pub value: String,
+}
+
+impl PaletteEntry {
+    pub fn new(label: impl Into<String>, expression: &str) -> Result<Self, CalcError> {
+        Ok(Self {
+            label: label.into(),
+            expression: normalize_expression(expression)?,
+        })
+    }
+}
+
+impl HistoryEntry {
+    pub fn new(expression: &str, value: impl Into<String>) -> Result<Self, CalcError> {
+        Ok(Self {
+            expression: normalize_expression(expression)?,
+            value: value.into(),
+        })
+    }
+}
+
+/// Checks that `expression` can be stored, failing if it is blank or doesn't tokenize. Complete
+/// expressions are formatted with their parentheses kept as typed (see `format_with`), while
+/// fragments that only tokenize (example: 'sin(') are kept as typed.
+pub fn normalize_expression(expression: &str) -> Result<String, CalcError> {
+    if expression.trim().is_empty() {
+        return Err(String::from("ERROR: cannot store an empty expression"));
+    }
+    crate::tokenize(expression)?;
+    let options = pretty::PrettyOptions {
+        preserve_groups: true,
+        ..Default::default()
+    };
+    let formatted = crate::format_with(expression, pretty::PrettyFormat::Spaced, options);
+    Ok(formatted.unwrap_or_else(|_| expression.trim().to_string()))
+}
@@ -61,6 +61,11 @@ impl<'a> Chomp<()> {
             matcher: matchers::is_whitespace,
         }
     }
+    pub fn whitespace_inline() -> Chomp<fn(&'a str) -> Option<usize>> {
+        Chomp {
+            matcher: matchers::is_whitespace_inline,
+        }
+    }
     pub fn alphabetic() -> Chomp<fn(&'a str) -> Option<usize>> {
         Chomp {
             matcher: matchers::is_alphabetic,
@@ -71,6 +76,16 @@ impl<'a> Chomp<()> {
             matcher: matchers::is_alphanumeric,
         }
     }
+    pub fn alphanumeric_extended() -> Chomp<fn(&'a str) -> Option<usize>> {
+        Chomp {
+            matcher: matchers::is_alphanumeric_extended,
+        }
+    }
+    pub fn superscript() -> Chomp<fn(&'a str) -> Option<usize>> {
+        Chomp {
+            matcher: matchers::is_superscript,
+        }
+    }
     pub fn numeric() -> Chomp<fn(&'a str) -> Option<usize>> {
         Chomp {
             matcher: matchers::is_numeric,
@@ -80,17 +95,17 @@ impl<'a> Chomp<()> {
         let mut seen_dp = false;
         Chomp {
             matcher: move |x: &str| {
+                // a sign or point needs digits to be a number (examples: '-', '..', but not '-2')
                 if x.split_once(|x: char| !x.is_ascii_digit() && !['.', '-', '−'].contains(&x))
-                    .map_or(false, |(x, _)| {
-                        x.len() <= 2 && !x.starts_with(|c| char::is_ascii_digit(&c))
-                    })
+                    .map_or(false, |(x, _)| !x.contains(|c| char::is_ascii_digit(&c)))
                 {
                     return None;
                 }
                 matchers::matches(
                     |z| match z {
                         (0, '-' | '−') => true,
-                        (_, '.') if !seen_dp => {
+                        // leaves '..' for the range operator (example: '1..4')
+                        (i, '.') if !seen_dp && !x[i + 1..].starts_with('.') => {
                             seen_dp = true;
                             true
                         }
@@ -102,11 +117,39 @@ impl<'a> Chomp<()> {
             },
         }
     }
+    pub fn string_literal() -> Chomp<fn(&'a str) -> Option<usize>> {
+        Chomp {
+            matcher: matchers::is_string_literal,
+        }
+    }
     pub fn literal(pattern: &'a str) -> Chomp<impl FnMut(&'a str) -> Option<usize>> {
         Chomp {
             matcher: move |x: &str| x.starts_with(pattern).then(|| pattern.len()),
         }
     }
+    // like 'literal', but not when the pattern only begins a longer identifier (example: 'printer')
+    pub fn keyword(pattern: &'a str) -> Chomp<impl FnMut(&'a str) -> Option<usize>> {
+        Chomp {
+            matcher: move |x: &str| {
+                let rest = x.strip_prefix(pattern)?;
+                matchers::is_alphanumeric_extended(rest)
+                    .filter(|len| *len == 0)
+                    .map(|_| pattern.len())
+            },
+        }
+    }
+    // like 'keyword', but only when called, leaving the bare name free for variables that share
+    // it (example: 'let beta = 0.5; beta(beta, 1)')
+    pub fn call(pattern: &'a str) -> Chomp<impl FnMut(&'a str) -> Option<usize>> {
+        let mut keyword = Self::keyword(pattern).matcher;
+        Chomp {
+            matcher: move |x: &'a str| {
+                let len = keyword(x)?;
+                let rest = x[len..].trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+                rest.starts_with('(').then_some(len)
+            },
+        }
+    }
     pub fn char(c: char) -> Chomp<impl Fn(&'a str) -> Option<usize>> {
         Chomp {
             matcher: move |x: &str| matchers::char_matches(move |(_, x)| *x == c, x),
@@ -146,19 +189,40 @@ impl<'a, M: ChompMatcher<'a>> Chomp<M> {
     }
 }
 
+pub const SUPERSCRIPT_DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
 mod matchers {
+    use super::SUPERSCRIPT_DIGITS;
+
     pub fn is_whitespace(x: &str) -> Option<usize> {
         matches(|(_, c)| c.is_whitespace(), x)
     }
+    pub fn is_whitespace_inline(x: &str) -> Option<usize> {
+        matches(|(_, c)| c.is_whitespace() && !matches!(c, '\n' | '\r'), x)
+    }
     pub fn is_alphabetic(x: &str) -> Option<usize> {
         matches(|(_, c)| c.is_alphabetic(), x)
     }
     pub fn is_alphanumeric(x: &str) -> Option<usize> {
         matches(|(_, c)| c.is_alphanumeric(), x)
     }
+    pub fn is_alphanumeric_extended(x: &str) -> Option<usize> {
+        // superscripts are numeric too, but are left to be read as powers (example: 'x²')
+        matches(
+            |(_, c)| (c.is_alphanumeric() || *c == '_') && !SUPERSCRIPT_DIGITS.contains(c),
+            x,
+        )
+    }
+    pub fn is_superscript(x: &str) -> Option<usize> {
+        matches(|(_, c)| SUPERSCRIPT_DIGITS.contains(c), x)
+    }
     pub fn is_numeric(x: &str) -> Option<usize> {
         matches(|(_, c)| c.is_numeric(), x)
     }
+    pub fn is_string_literal(x: &str) -> Option<usize> {
+        let rest = x.strip_prefix('"')?;
+        rest.find('"').map(|end| end + 2)
+    }
     pub fn matches(f: impl FnMut(&(usize, char)) -> bool, x: &str) -> Option<usize> {
         x.char_indices()
             .chain(std::iter::once((x.len(), '\x00')))
@@ -0,0 +1,4 @@
+// the stable public surface of the crate, for glob importing with `use xpress_calc::prelude::*;`
+
+pub use crate::vm::{Instruction, StackItem, VM};
+pub use crate::{compile, compute, eval, format, CalcError};
@@ -1,10 +1,21 @@
 use std::fmt::Write;
 
-use crate::compiler::{BinaryOp, Func0Op, Func1Op, RecursiveExpression};
+use crate::compiler::{BinaryOp, Func0Op, Func1Op, Func2Op, Func3Op, FuncNOp, RecursiveExpression};
 
-pub(crate) fn pretty_print(program_expression: RecursiveExpression, which: PrettyFormat) -> String {
+pub fn pretty_print(
+    program_expression: RecursiveExpression,
+    which: PrettyFormat,
+    options: PrettyOptions,
+) -> String {
     let mut pretty_output = String::new();
-    delve(&program_expression, None, &mut pretty_output, 0, which);
+    delve(
+        &program_expression,
+        None,
+        &mut pretty_output,
+        0,
+        which,
+        options,
+    );
 
     fn delve(
         inner: &RecursiveExpression,
@@ -12,14 +23,26 @@ pub(crate) fn pretty_print(program_expression: RecursiveExpression, which: Prett
         output: &mut String,
         indent: usize,
         which: PrettyFormat,
+        options: PrettyOptions,
     ) {
         match inner {
+            RecursiveExpression::Sequence(statements) => {
+                statements.iter().for_each(|node| {
+                    delve(node, Some(inner), output, indent, which, options);
+                    output.push(';');
+                    which.push_newline(output, indent);
+                });
+
+                *output = output
+                    .trim_end_matches(|c| matches!(c, ';' | '\n' | ' '))
+                    .to_string();
+            }
             RecursiveExpression::Block(statements) => {
                 output.push('{');
                 which.push_newline(output, indent + 1);
 
                 statements.iter().for_each(|node| {
-                    delve(node, Some(inner), output, indent + 1, which);
+                    delve(node, Some(inner), output, indent + 1, which, options);
                     output.push_str(";");
                     which.push_newline(output, indent + 1);
                 });
@@ -31,13 +54,76 @@ pub(crate) fn pretty_print(program_expression: RecursiveExpression, which: Prett
                 which.push_newline(output, indent);
                 output.push('}');
             }
+            RecursiveExpression::Group(grouped) => {
+                output.push('(');
+                delve(grouped, Some(inner), output, indent, which, options);
+                output.push(')');
+            }
+            RecursiveExpression::Literal(x) if options.unicode_operators && *x < 0.0 => {
+                write!(output, "−{}", -x).unwrap()
+            }
             RecursiveExpression::Literal(x) => write!(output, "{x}").unwrap(),
-            RecursiveExpression::Local(ident) => output.push_str(ident),
+            RecursiveExpression::LiteralInt(x) if options.unicode_operators && *x < 0 => {
+                write!(output, "−{}", x.unsigned_abs()).unwrap()
+            }
+            RecursiveExpression::LiteralInt(x) => write!(output, "{x}").unwrap(),
+            RecursiveExpression::LiteralStr(x) => write!(output, "\"{x}\"").unwrap(),
+            RecursiveExpression::Array(elements) => {
+                output.push('[');
+                elements.iter().for_each(|node| {
+                    delve(node, Some(inner), output, indent, which, options);
+                    output.push(',');
+                    which.push_space(output);
+                });
+                *output = output
+                    .trim_end_matches(|c| matches!(c, ',' | ' '))
+                    .to_string();
+                output.push(']');
+            }
+            RecursiveExpression::Tuple(elements) => {
+                output.push('(');
+                elements.iter().for_each(|node| {
+                    delve(node, Some(inner), output, indent, which, options);
+                    output.push(',');
+                    which.push_space(output);
+                });
+                *output = output
+                    .trim_end_matches(|c| matches!(c, ',' | ' '))
+                    .to_string();
+                // without its trailing comma a 1-tuple would read back as a parenthesized
+                // expression
+                if elements.len() == 1 {
+                    output.push(',');
+                }
+                output.push(')');
+            }
+            RecursiveExpression::Range(start, end, inclusive) => {
+                let requires_parens = matches!(
+                    parent,
+                    Some(
+                        RecursiveExpression::BinaryOp(..)
+                            | RecursiveExpression::Not(_)
+                            | RecursiveExpression::Factorial(_)
+                            | RecursiveExpression::Index(..)
+                    )
+                );
+                if requires_parens {
+                    output.push('(');
+                }
+                delve(start, Some(inner), output, indent, which, options);
+                output.push_str(if *inclusive { "..=" } else { ".." });
+                delve(end, Some(inner), output, indent, which, options);
+                if requires_parens {
+                    output.push(')');
+                }
+            }
+            RecursiveExpression::Local(ident) => push_ident(output, ident, options),
             RecursiveExpression::FuncDeclaration(params, body) => {
                 output.push('(');
                 let params_iter = params.iter();
                 params_iter.for_each(|ident| {
-                    write!(output, "{ident},").unwrap();
+                    push_ident(output, ident, options);
+                    output.push(',');
                     which.push_space(output);
                 });
                 *output = output
@@ -47,46 +133,119 @@ pub(crate) fn pretty_print(program_expression: RecursiveExpression, which: Prett
                 which.push_space(output);
                 output.push_str("=>");
                 which.push_space(output);
-                delve(body, Some(inner), output, indent, which);
+                delve(body, Some(inner), output, indent, which, options);
             }
             RecursiveExpression::If(condition, block) => {
                 output.push_str("if (");
-                delve(condition, Some(inner), output, indent, which);
+                delve(condition, Some(inner), output, indent, which, options);
                 output.push_str(") ");
-                delve(block, Some(inner), output, indent, which);
+                delve(block, Some(inner), output, indent, which, options);
             }
             RecursiveExpression::IfElse(condition, if_block, else_block) => {
                 output.push_str("if (");
-                delve(condition, Some(inner), output, indent, which);
+                delve(condition, Some(inner), output, indent, which, options);
                 output.push_str(") ");
-                delve(if_block, Some(inner), output, indent, which);
+                delve(if_block, Some(inner), output, indent, which, options);
                 output.push_str(" else ");
-                delve(else_block, Some(inner), output, indent, which);
+                delve(else_block, Some(inner), output, indent, which, options);
+            }
+            RecursiveExpression::AssignOp(ident, value)
+            | RecursiveExpression::ConstAssign(ident, value) => {
+                match inner {
+                    RecursiveExpression::ConstAssign(..) => output.push_str("const "),
+                    _ => output.push_str("let "),
+                }
+                push_ident(output, ident, options);
+                which.push_space(output);
+                output.push('=');
+                which.push_space(output);
+                delve(value, Some(inner), output, indent, which, options);
+            }
+            RecursiveExpression::AssignExpr(ident, value) => {
+                // the value extends as far right as it can, so it must be closed off inside others
+                let requires_parens = matches!(
+                    parent,
+                    Some(
+                        RecursiveExpression::BinaryOp(..)
+                            | RecursiveExpression::Range(..)
+                            | RecursiveExpression::Not(_)
+                            | RecursiveExpression::Factorial(_)
+                            | RecursiveExpression::Index(..)
+                    )
+                );
+                if requires_parens {
+                    output.push('(');
+                }
+                push_ident(output, ident, options);
+                which.push_space(output);
+                output.push_str(":=");
+                which.push_space(output);
+                delve(value, Some(inner), output, indent, which, options);
+                if requires_parens {
+                    output.push(')');
+                }
+            }
+            RecursiveExpression::DestructureAssign(idents, value) => {
+                output.push_str("let ");
+                idents.iter().enumerate().for_each(|(idx, ident)| {
+                    if idx > 0 {
+                        output.push(',');
+                        which.push_space(output);
+                    }
+                    push_ident(output, ident, options);
+                });
+                which.push_space(output);
+                output.push('=');
+                which.push_space(output);
+                delve(value, Some(inner), output, indent, which, options);
             }
-            RecursiveExpression::AssignOp(ident, value) => {
-                write!(output, "let {ident}").unwrap();
+            RecursiveExpression::IndexAssign(ident, index, value) => {
+                push_ident(output, ident, options);
+                output.push('[');
+                delve(index, None, output, indent, which, options);
+                output.push(']');
                 which.push_space(output);
                 output.push('=');
                 which.push_space(output);
-                delve(value, Some(inner), output, indent, which);
+                delve(value, Some(inner), output, indent, which, options);
             }
             RecursiveExpression::BinaryOp(lhs, op, rhs) => {
                 let requires_parens = match parent {
-                    Some(RecursiveExpression::BinaryOp(_, parent_op, _)) => {
+                    Some(RecursiveExpression::BinaryOp(parent_lhs, parent_op, _)) => {
                         let precedence = op.precedence();
-                        parent_op.precedence() != precedence && precedence < 3
+                        let is_lhs = std::ptr::eq(parent_lhs.as_ref(), inner);
+                        // powers group to the right, so a power used as a base needs parentheses
+                        let is_base = matches!(parent_op, BinaryOp::Pow) && is_lhs;
+                        // the rest group to the left, which only '+' and '*' can ignore on the
+                        // right (example: '8 - (4 - 2)')
+                        let is_regrouped = !is_lhs
+                            && parent_op.precedence() == precedence
+                            && !matches!(parent_op, BinaryOp::Add | BinaryOp::Mul | BinaryOp::Pow);
+                        is_base
+                            || is_regrouped
+                            || parent_op.precedence() != precedence
+                                && precedence < BinaryOp::Pow.precedence()
                     }
+                    Some(
+                        RecursiveExpression::Not(_)
+                        | RecursiveExpression::Factorial(_)
+                        | RecursiveExpression::Index(..),
+                    ) => true,
                     _ => false,
                 };
                 if requires_parens {
                     output.push('(');
                 }
-                delve(lhs, Some(inner), output, indent, which);
+                delve(lhs, Some(inner), output, indent, which, options);
                 let op_str = match op {
                     BinaryOp::Add => " + ",
+                    BinaryOp::Sub if options.unicode_operators => " − ",
                     BinaryOp::Sub => " - ",
+                    BinaryOp::Div if options.unicode_operators => " ÷ ",
                     BinaryOp::Div => " / ",
+                    BinaryOp::Mul if options.unicode_operators => " × ",
                     BinaryOp::Mul => " * ",
+                    BinaryOp::Mod if options.prefer_mod_keyword => " mod ",
                     BinaryOp::Mod => " % ",
                     BinaryOp::Pow => "^",
                     BinaryOp::EQ => " == ",
@@ -96,17 +255,36 @@ pub(crate) fn pretty_print(program_expression: RecursiveExpression, which: Prett
                     BinaryOp::GT => " > ",
                     BinaryOp::GTE => " >= ",
                 };
+                let is_keyword = op_str.trim().starts_with(char::is_alphabetic);
                 match which {
+                    // keywords still need separating from their operands (example: 'a mod b')
+                    PrettyFormat::Minified if is_keyword => output.push_str(op_str),
                     PrettyFormat::Minified => output.push_str(op_str.trim()),
                     PrettyFormat::Spaced | PrettyFormat::Indented => output.push_str(op_str),
                 }
-                delve(rhs, Some(inner), output, indent, which);
+                delve(rhs, Some(inner), output, indent, which, options);
                 if requires_parens {
                     output.push(')');
                 }
             }
+            RecursiveExpression::Not(value) => {
+                output.push('!');
+                delve(value, Some(inner), output, indent, which, options);
+            }
+            RecursiveExpression::Factorial(value) => {
+                delve(value, Some(inner), output, indent, which, options);
+                output.push('!');
+            }
+            RecursiveExpression::Index(target, index) => {
+                delve(target, Some(inner), output, indent, which, options);
+                output.push('[');
+                // the brackets already delimit the index, so it never needs parens
+                delve(index, None, output, indent, which, options);
+                output.push(']');
+            }
             RecursiveExpression::Func0(op) => match op {
                 Func0Op::Rand => output.push_str("rand()"),
+                Func0Op::RandNormal => output.push_str("rand_normal()"),
             },
             RecursiveExpression::Func1(op, value) => {
                 match op {
@@ -116,14 +294,80 @@ pub(crate) fn pretty_print(program_expression: RecursiveExpression, which: Prett
                     Func1Op::Log => output.push_str("log("),
                     Func1Op::Round => output.push_str("round("),
                     Func1Op::Floor => output.push_str("floor("),
+                    Func1Op::Log2 => output.push_str("log2("),
+                    Func1Op::Csc => output.push_str("csc("),
+                    Func1Op::Sec => output.push_str("sec("),
+                    Func1Op::Cot => output.push_str("cot("),
+                    Func1Op::Erfc => output.push_str("erfc("),
+                    Func1Op::Erf => output.push_str("erf("),
+                    Func1Op::Gamma => output.push_str("gamma("),
+                    Func1Op::Print => output.push_str("print("),
+                    Func1Op::Clamp01 => output.push_str("clamp01("),
+                }
+                delve(value, Some(inner), output, indent, which, options);
+                output.push(')');
+            }
+            RecursiveExpression::Func2(op, arg1, arg2) => {
+                match op {
+                    Func2Op::Map => output.push_str("map("),
+                    Func2Op::Simulate => output.push_str("simulate("),
+                    Func2Op::Beta => output.push_str("beta("),
+                    Func2Op::Pow => output.push_str("pow("),
+                    Func2Op::RandNormal => output.push_str("rand_normal("),
+                }
+                delve(arg1, Some(inner), output, indent, which, options);
+                output.push(',');
+                which.push_space(output);
+                delve(arg2, Some(inner), output, indent, which, options);
+                output.push(')');
+            }
+            RecursiveExpression::Func3(op, arg1, arg2, arg3) => {
+                match op {
+                    Func3Op::Reduce => output.push_str("reduce("),
+                    Func3Op::Lerp => output.push_str("lerp("),
                 }
-                delve(value, Some(inner), output, indent, which);
+                delve(arg1, Some(inner), output, indent, which, options);
+                output.push(',');
+                which.push_space(output);
+                delve(arg2, Some(inner), output, indent, which, options);
+                output.push(',');
+                which.push_space(output);
+                delve(arg3, Some(inner), output, indent, which, options);
+                output.push(')');
+            }
+            RecursiveExpression::FuncN(op, args) => {
+                match op {
+                    FuncNOp::Min => output.push_str("min("),
+                    FuncNOp::Max => output.push_str("max("),
+                }
+                args.iter().for_each(|node| {
+                    delve(node, Some(inner), output, indent, which, options);
+                    output.push(',');
+                    which.push_space(output);
+                });
+                *output = output
+                    .trim_end_matches(|c| matches!(c, ',' | ' '))
+                    .to_string();
+                output.push(')');
+            }
+            RecursiveExpression::Call(target, args) => {
+                delve(target, Some(inner), output, indent, which, options);
+                output.push('(');
+                args.iter().for_each(|node| {
+                    delve(node, Some(inner), output, indent, which, options);
+                    output.push(',');
+                    which.push_space(output);
+                });
+                *output = output
+                    .trim_end_matches(|c| matches!(c, ',' | ' '))
+                    .to_string();
                 output.push(')');
             }
             RecursiveExpression::FuncLocal(ident, args) => {
-                write!(output, "{ident}(").unwrap();
+                push_ident(output, ident, options);
+                output.push('(');
                 args.iter().for_each(|node| {
-                    delve(node, Some(inner), output, indent, which);
+                    delve(node, Some(inner), output, indent, which, options);
                     output.push_str(",");
                     which.push_space(output);
                 });
@@ -145,6 +389,35 @@ pub enum PrettyFormat {
     Indented,
 }
 
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PrettyOptions {
+    /// Prints numbered identifiers such as `x_1` with Unicode subscripts (example: `x₁`).
+    pub unicode_subscripts: bool,
+    /// Emits modulo as the `mod` keyword rather than `%`.
+    pub prefer_mod_keyword: bool,
+    /// Prints parentheses exactly where the user wrote them instead of re-deriving them.
+    pub preserve_groups: bool,
+    /// Emits `×`, `÷` and `−` in place of their ASCII operators.
+    pub unicode_operators: bool,
+}
+
+fn push_ident(output: &mut String, ident: &str, options: PrettyOptions) {
+    let subscript = ident.rsplit_once('_').filter(|(name, digits)| {
+        !name.is_empty() && !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+    });
+    match subscript {
+        Some((name, digits)) if options.unicode_subscripts => {
+            output.push_str(name);
+            // the subscript digits '₀'..='₉' are contiguous, just like their ascii counterparts
+            let subscripts = digits
+                .chars()
+                .filter_map(|c| char::from_u32('₀' as u32 + c.to_digit(10)?));
+            output.extend(subscripts);
+        }
+        _ => output.push_str(ident),
+    }
+}
+
 impl PrettyFormat {
     fn push_newline(&self, output: &mut String, indent: usize) {
         match self {
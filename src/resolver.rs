@@ -0,0 +1,152 @@
+use crate::compiler::RecursiveExpression;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentKind {
+    /// Called directly (example: the 'f' in 'f(x)').
+    Call,
+    /// Read as a value.
+    Value,
+    /// Read as a value, but bound to a function declared in the same program (example: the 'f' in
+    /// 'let f = (x) => x; map(a, f)').
+    FunctionRef,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentUse {
+    pub name: String,
+    pub kind: IdentKind,
+}
+
+/// Classifies every identifier that is read or called in `expression`, in source order. Names
+/// that are only bound (let targets and parameters) are not uses, so they aren't listed.
+pub fn resolve(expression: &RecursiveExpression) -> Vec<IdentUse> {
+    let mut functions = vec![];
+    collect_functions(expression, &mut functions);
+    let mut uses = vec![];
+    delve(expression, &functions, &mut uses);
+    uses
+}
+
+/// Names bound by a 'let' in a nested scope (a block or function body) while a binding of the
+/// same name is visible from an enclosing one, in source order. As scoping is dynamic, such a
+/// 'let' overwrites the outer binding rather than hiding it.
+pub fn find_shadowing(expression: &RecursiveExpression) -> Vec<String> {
+    let mut scopes = vec![vec![]];
+    let mut shadowed = vec![];
+    delve_scopes(expression, &mut scopes, &mut shadowed);
+    shadowed
+}
+
+fn delve_scopes(
+    expression: &RecursiveExpression,
+    scopes: &mut Vec<Vec<String>>,
+    shadowed: &mut Vec<String>,
+) {
+    let mut bind = |ident: &String, scopes: &mut Vec<Vec<String>>| {
+        let (scope, enclosing) = scopes.split_last_mut().unwrap();
+        if scope.contains(ident) {
+            return;
+        }
+        if enclosing.iter().any(|scope| scope.contains(ident)) {
+            shadowed.push(ident.clone());
+        }
+        scope.push(ident.clone());
+    };
+    match expression {
+        RecursiveExpression::Block(statements) => {
+            scopes.push(vec![]);
+            statements
+                .iter()
+                .for_each(|node| delve_scopes(node, scopes, shadowed));
+            scopes.pop();
+            return;
+        }
+        RecursiveExpression::FuncDeclaration(params, body) => {
+            scopes.push(params.clone());
+            delve_scopes(body, scopes, shadowed);
+            scopes.pop();
+            return;
+        }
+        // bound ahead of the value, which may refer back to it (example: a recursive function)
+        RecursiveExpression::AssignOp(ident, _)
+        | RecursiveExpression::ConstAssign(ident, _)
+        | RecursiveExpression::AssignExpr(ident, _) => bind(ident, scopes),
+        RecursiveExpression::DestructureAssign(idents, _) => {
+            idents.iter().for_each(|ident| bind(ident, scopes))
+        }
+        _ => {}
+    }
+    children(expression)
+        .into_iter()
+        .for_each(|node| delve_scopes(node, scopes, shadowed));
+}
+
+fn collect_functions(expression: &RecursiveExpression, functions: &mut Vec<String>) {
+    if let RecursiveExpression::AssignOp(ident, value)
+    | RecursiveExpression::ConstAssign(ident, value)
+    | RecursiveExpression::AssignExpr(ident, value) = expression
+    {
+        if let RecursiveExpression::FuncDeclaration(..) = value.as_ref() {
+            functions.push(ident.clone());
+        }
+    }
+    children(expression)
+        .into_iter()
+        .for_each(|node| collect_functions(node, functions));
+}
+
+fn delve(expression: &RecursiveExpression, functions: &[String], uses: &mut Vec<IdentUse>) {
+    let ident_use = match expression {
+        RecursiveExpression::FuncLocal(ident, _) => Some((ident, IdentKind::Call)),
+        RecursiveExpression::Local(ident) if functions.contains(ident) => {
+            Some((ident, IdentKind::FunctionRef))
+        }
+        RecursiveExpression::Local(ident) => Some((ident, IdentKind::Value)),
+        _ => None,
+    };
+    if let Some((ident, kind)) = ident_use {
+        uses.push(IdentUse {
+            name: ident.clone(),
+            kind,
+        });
+    }
+    children(expression)
+        .into_iter()
+        .for_each(|node| delve(node, functions, uses));
+}
+
+pub(crate) fn children(expression: &RecursiveExpression) -> Vec<&RecursiveExpression> {
+    match expression {
+        RecursiveExpression::Sequence(nodes)
+        | RecursiveExpression::Block(nodes)
+        | RecursiveExpression::Tuple(nodes)
+        | RecursiveExpression::Array(nodes)
+        | RecursiveExpression::FuncN(_, nodes)
+        | RecursiveExpression::FuncLocal(_, nodes) => nodes.iter().collect(),
+        RecursiveExpression::Literal(_)
+        | RecursiveExpression::LiteralInt(_)
+        | RecursiveExpression::LiteralStr(_)
+        | RecursiveExpression::Local(_)
+        | RecursiveExpression::Func0(_) => vec![],
+        RecursiveExpression::Group(node)
+        | RecursiveExpression::FuncDeclaration(_, node)
+        | RecursiveExpression::AssignOp(_, node)
+        | RecursiveExpression::ConstAssign(_, node)
+        | RecursiveExpression::AssignExpr(_, node)
+        | RecursiveExpression::DestructureAssign(_, node)
+        | RecursiveExpression::Not(node)
+        | RecursiveExpression::Factorial(node)
+        | RecursiveExpression::Func1(_, node) => vec![node],
+        RecursiveExpression::Range(lhs, rhs, _)
+        | RecursiveExpression::If(lhs, rhs)
+        | RecursiveExpression::IndexAssign(_, lhs, rhs)
+        | RecursiveExpression::BinaryOp(lhs, _, rhs)
+        | RecursiveExpression::Index(lhs, rhs)
+        | RecursiveExpression::Func2(_, lhs, rhs) => vec![lhs, rhs],
+        RecursiveExpression::IfElse(arg1, arg2, arg3)
+        | RecursiveExpression::Func3(_, arg1, arg2, arg3) => vec![arg1, arg2, arg3],
+        RecursiveExpression::Call(target, args) => {
+            std::iter::once(target.as_ref()).chain(args).collect()
+        }
+    }
+}
@@ -1,4 +1,20 @@
-use std::{cell::RefCell, rc::Rc};
+use crate::{
+    compiler, decompiler,
+    lexer::Token,
+    pretty::{self, PrettyFormat},
+};
+
+use std::hash::{Hash, Hasher};
+
+#[cfg(feature = "sync")]
+use std::sync::{Arc as Shared, Mutex};
+#[cfg(not(feature = "sync"))]
+use std::{cell::RefCell, rc::Rc as Shared};
+
+// caps how many elements a single array (such as a materialized range) may hold by default
+const DEFAULT_MAX_ARRAY_LEN: usize = 1_000_000;
+// caps how many times a single 'simulate(f, n)' may call its function by default
+const DEFAULT_MAX_SIMULATE_RUNS: usize = 100_000;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Instruction {
@@ -9,15 +25,40 @@ pub enum Instruction {
     Log,
     Round,
     Floor,
+    Sqrt,
+    Log2,
+    Csc,
+    Sec,
+    Cot,
+    Erfc,
+    Erf,
+    Gamma,
+    Print,
+    Clamp01,
     Push(f64),
+    PushConst(usize),
+    PushInt(i64),
+    PushStr(String),
+    MakeArray(usize),
+    MakeTuple(usize),
+    Destructure(Vec<String>),
+    Range(bool),
     Assign(String),
+    AssignConst(String),
     ShadowAssign(String),
     LoadLocal(String),
-    CallRoutine,
+    // calls expect their arguments pushed first-to-last beneath the callee. a routine begins with
+    // one 'ShadowAssign' per parameter, last-to-first, which pops the arguments back off in reverse
+    CallRoutine(String, usize),
+    TailCallRoutine(usize),
+    CallValue(usize),
     PushRoutine(Vec<Instruction>),
     SkipIfNot(Vec<Instruction>),
     IfElse(Vec<Instruction>, Vec<Instruction>),
     PushRandom,
+    PushRandomNormal,
+    // pops the standard deviation, then the mean
+    RandomNormal,
     Mul,
     Mod,
     Div,
@@ -30,20 +71,307 @@ pub enum Instruction {
     CmpGTE,
     Enter,
     Leave,
+    Dup,
+    Pop,
+    Swap,
+    Discard,
+    AssertStackEmpty,
+    Not,
+    Factorial,
+    Map,
+    Simulate,
+    Reduce,
+    Lerp,
+    Index,
+    IndexSet(String),
+    Min,
+    Max,
+    Beta,
+}
+
+// NaN literals break reflexivity, but the compiler never emits them so programs compare sanely
+impl Eq for Instruction {}
+
+impl Hash for Instruction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Instruction::Push(x) => {
+                // -0.0 == 0.0 so both need to land in the same bucket
+                let x = if *x == 0.0 { 0.0 } else { *x };
+                x.to_bits().hash(state)
+            }
+            Instruction::PushInt(x) => x.hash(state),
+            Instruction::PushStr(x)
+            | Instruction::Assign(x)
+            | Instruction::AssignConst(x)
+            | Instruction::ShadowAssign(x)
+            | Instruction::LoadLocal(x)
+            | Instruction::IndexSet(x) => x.hash(state),
+            Instruction::CallRoutine(x, arity) => {
+                x.hash(state);
+                arity.hash(state);
+            }
+            Instruction::Destructure(x) => x.hash(state),
+            Instruction::PushConst(x)
+            | Instruction::MakeArray(x)
+            | Instruction::MakeTuple(x)
+            | Instruction::TailCallRoutine(x)
+            | Instruction::CallValue(x) => x.hash(state),
+            Instruction::Range(x) => x.hash(state),
+            Instruction::PushRoutine(x) | Instruction::SkipIfNot(x) => x.hash(state),
+            Instruction::IfElse(x, y) => {
+                x.hash(state);
+                y.hash(state);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Borrows a compiled program so it can be used as a `HashMap`/`HashSet` key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProgramKey<'a>(pub &'a [Instruction]);
+
+#[derive(Debug, Clone)]
+pub struct FunctionHandle {
+    name: String,
+    arity: usize,
+    routine: Vec<Instruction>,
+}
+
+impl FunctionHandle {
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionInfo {
+    pub params: Vec<String>,
+    pub body: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ProgramStats {
+    pub instruction_count: usize,
+    pub max_depth: usize,
+    pub uses_random: bool,
+    pub calls_routines: bool,
+}
+
+/// Summarises how heavy a compiled program is, counting instructions inside nested routines and
+/// branches as well as the top level.
+pub fn program_stats(program: &[Instruction]) -> ProgramStats {
+    fn delve(program: &[Instruction], depth: usize, stats: &mut ProgramStats) {
+        stats.max_depth = stats.max_depth.max(depth);
+        for instruction in program {
+            stats.instruction_count += 1;
+            match instruction {
+                Instruction::PushRoutine(routine) | Instruction::SkipIfNot(routine) => {
+                    delve(routine, depth + 1, stats)
+                }
+                Instruction::IfElse(if_block, else_block) => {
+                    delve(if_block, depth + 1, stats);
+                    delve(else_block, depth + 1, stats);
+                }
+                Instruction::PushRandom
+                | Instruction::PushRandomNormal
+                | Instruction::RandomNormal => stats.uses_random = true,
+                Instruction::CallRoutine(..)
+                | Instruction::TailCallRoutine(_)
+                | Instruction::CallValue(_) => stats.calls_routines = true,
+                _ => (),
+            }
+        }
+    }
+
+    let mut stats = ProgramStats::default();
+    delve(program, 0, &mut stats);
+    stats
+}
+
+/// A program whose number literals are stored once in `constants` and loaded by index with
+/// `PushConst`, see `pool_constants`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PooledProgram {
+    pub constants: Vec<f64>,
+    pub instructions: Vec<Instruction>,
+}
+
+/// Moves every `Push` literal of `program` into a deduplicated constant pool. Function bodies keep
+/// their literals inline, since a function can outlive the program (and pool) that defined it.
+pub fn pool_constants(program: &[Instruction]) -> PooledProgram {
+    fn delve(program: &[Instruction], constants: &mut Vec<f64>) -> Vec<Instruction> {
+        let pooled = program.iter().map(|instruction| match instruction {
+            Instruction::Push(x) => {
+                // compared by bits so that '0.0' and '-0.0' stay distinct
+                let idx = constants.iter().position(|c| c.to_bits() == x.to_bits());
+                let idx = idx.unwrap_or_else(|| {
+                    constants.push(*x);
+                    constants.len() - 1
+                });
+                Instruction::PushConst(idx)
+            }
+            Instruction::SkipIfNot(block) => Instruction::SkipIfNot(delve(block, constants)),
+            Instruction::IfElse(if_block, else_block) => {
+                Instruction::IfElse(delve(if_block, constants), delve(else_block, constants))
+            }
+            instruction => instruction.clone(),
+        });
+        pooled.collect()
+    }
+
+    let mut constants = vec![];
+    let instructions = delve(program, &mut constants);
+    PooledProgram {
+        constants,
+        instructions,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// A function provided by the calculator (example: 'sin'), including registered natives.
+    Builtin,
+    /// A builtin constant (example: 'pi').
+    Constant,
+    /// A variable in scope holding a function.
+    Function,
+    /// Any other variable in scope.
+    Variable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub name: String,
+    pub kind: CompletionKind,
+}
+
+/// One instruction executed by `VM::run_traced`, with the top of the stack it left behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceStep {
+    pub instruction: Instruction,
+    pub top: Option<StackItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackItem {
+    Number(f64),
+    Int(i64),
+    Str(String),
+    Array(Vec<StackItem>),
+    Tuple(Vec<StackItem>),
+    Routine { arity: usize },
+}
+
+pub type NativeFn = fn(&[f64]) -> Result<f64, String>;
+
+/// What reading a variable that was never assigned evaluates to.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MissingPolicy {
+    #[default]
+    Zero,
+    Nan,
+    Error,
+}
+
+/// What integer arithmetic does when its result doesn't fit in an i64.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum IntOverflow {
+    Wrap,
+    Saturate,
+    #[default]
+    Error,
+}
+
+/// The precision numbers are rounded to after every instruction.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Precision {
+    #[default]
+    F64,
+    /// Approximates f32 arithmetic (example: for matching embedded hardware) while still storing
+    /// f64s, by rounding every result to the nearest f32.
+    F32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Native {
+    arity: usize,
+    func: NativeFn,
 }
 
 #[derive(Debug, Clone)]
 enum Value {
     Number(f64),
+    Int(i64),
     Routine(Vec<Instruction>),
+    Native(Native),
+    Array(Vec<Value>),
+    Tuple(Vec<Value>),
+    Str(String),
 }
 
 impl Value {
     fn as_number(&self) -> f64 {
         match self {
             Self::Number(v) => *v,
+            Self::Int(v) => *v as f64,
             Self::Routine(routine) if !routine.is_empty() => 1.0,
             Self::Routine(_) => 0.0,
+            Self::Native(_) => 1.0,
+            Self::Array(_) | Self::Tuple(_) | Self::Str(_) => f64::NAN,
+        }
+    }
+
+    fn round_to_f32(&mut self) {
+        match self {
+            Self::Number(x) => *x = *x as f32 as f64,
+            Self::Array(elements) | Self::Tuple(elements) => {
+                elements.iter_mut().for_each(Value::round_to_f32)
+            }
+            _ => {}
+        }
+    }
+
+    fn is_function(&self) -> bool {
+        matches!(self, Self::Routine(_) | Self::Native(_))
+    }
+
+    fn to_stack_item(&self) -> StackItem {
+        match self {
+            Self::Number(x) => StackItem::Number(*x),
+            Self::Int(x) => StackItem::Int(*x),
+            Self::Str(x) => StackItem::Str(x.clone()),
+            Self::Array(elements) => {
+                StackItem::Array(elements.iter().map(Value::to_stack_item).collect())
+            }
+            Self::Tuple(elements) => {
+                StackItem::Tuple(elements.iter().map(Value::to_stack_item).collect())
+            }
+            Self::Routine(routine) => {
+                let params = routine.iter();
+                let arity = params
+                    .take_while(|x| matches!(x, Instruction::ShadowAssign(_)))
+                    .count();
+                StackItem::Routine { arity }
+            }
+            Self::Native(native) => StackItem::Routine {
+                arity: native.arity,
+            },
+        }
+    }
+
+    fn to_concat_string(&self) -> Result<String, String> {
+        match self {
+            Self::Str(x) => Ok(x.clone()),
+            Self::Number(x) => Ok(format_number(*x)),
+            Self::Int(x) => Ok(x.to_string()),
+            Self::Routine(_) | Self::Native(_) => {
+                Err(String::from("cannot concatenate a function"))
+            }
+            Self::Array(_) => Err(String::from("cannot concatenate an array")),
+            Self::Tuple(_) => Err(String::from("cannot concatenate a tuple")),
         }
     }
 }
@@ -64,7 +392,28 @@ impl From<f64> for Value {
 pub struct VM {
     stack: Vec<Value>,
     scopes: ScopeStack,
-    rng: Rc<Rand>,
+    // clones of a VM draw from the same random sequence
+    rng: Shared<Rand>,
+    // Box-Muller yields normal samples in pairs, the second is kept for the next draw
+    normal_spare: Option<f64>,
+    strict_math: bool,
+    missing_default: MissingPolicy,
+    int_overflow: IntOverflow,
+    precision: Precision,
+    tail_call: Option<Vec<Instruction>>,
+    call_stack: Vec<String>,
+    error_trace: Option<Vec<String>>,
+    omit_stack_trace: bool,
+    debug_checks: bool,
+    // the stack height when the current 'run' began, below which values belong to earlier runs
+    run_height: usize,
+    trace: Option<Vec<TraceStep>>,
+    constants: Vec<f64>,
+    output: Vec<StackItem>,
+    max_array_len: Option<usize>,
+    max_simulate_runs: Option<usize>,
+    warnings: Vec<String>,
+    natives: NativeRegistry,
 }
 
 impl VM {
@@ -72,21 +421,319 @@ impl VM {
         Self::default()
     }
 
+    /// In strict mode, domain errors such as division by zero or the square root of a negative
+    /// number fail the program instead of producing `inf`/`NaN`, as do factorials and gamma
+    /// values too large to represent.
+    pub fn set_strict_math(&mut self, strict: bool) {
+        self.strict_math = strict;
+    }
+
+    /// Sets what a missing variable evaluates to. Both `Zero` and `Nan` still raise a warning.
+    pub fn set_missing_default(&mut self, policy: MissingPolicy) {
+        self.missing_default = policy;
+    }
+
+    /// Sets how integer add, subtract, multiply and power handle results beyond the range of an
+    /// i64 (see `CompileOptions::integer_literals`).
+    pub fn set_int_overflow(&mut self, policy: IntOverflow) {
+        self.int_overflow = policy;
+    }
+
+    /// Limits how many elements an array literal or range may create, failing the program rather
+    /// than allocating past it. Defaults to 1,000,000.
+    pub fn set_max_array_len(&mut self, max_array_len: usize) {
+        self.max_array_len = Some(max_array_len);
+    }
+
+    /// Limits how many runs a single `simulate(f, n)` may make, failing the program rather than
+    /// calling `f` past it. Defaults to 100,000.
+    pub fn set_max_simulate_runs(&mut self, max_simulate_runs: usize) {
+        self.max_simulate_runs = Some(max_simulate_runs);
+    }
+
+    fn check_array_len(&self, len: usize) -> Result<(), String> {
+        let max_array_len = self.max_array_len.unwrap_or(DEFAULT_MAX_ARRAY_LEN);
+        if len > max_array_len {
+            return Err(format!(
+                "array of {len} elements exceeds the limit of {max_array_len}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Restarts the sequence drawn by `rand()` and `rand_normal()` from `seed`, so runs repeat.
+    /// VMs start from a seed of 0.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Shared::new(Rand::from_seed(seed));
+        self.normal_spare = None;
+    }
+
+    // draws from the standard normal distribution
+    fn rand_normal(&mut self) -> f64 {
+        if let Some(z) = self.normal_spare.take() {
+            return z;
+        }
+        // 'rand' is in [0, 1), so flip it to keep the logarithm finite
+        let radius = (-2.0 * (1.0 - self.rng.rand()).ln()).sqrt();
+        let theta = std::f64::consts::TAU * self.rng.rand();
+        self.normal_spare = Some(radius * theta.sin());
+        radius * theta.cos()
+    }
+
+    /// Sets the precision of number results, see `Precision`. Integers are unaffected.
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+    }
+
+    /// Controls whether runtime errors raised inside function calls are prefixed with the chain of
+    /// calls that led to them (example: 'in f -> g: division by zero').
+    pub fn set_stack_trace(&mut self, enabled: bool) {
+        self.omit_stack_trace = !enabled;
+    }
+
+    /// Appends an `AssertStackEmpty` check to every program run, failing it if the run left
+    /// anything of its own below its result. This catches miscompiled programs that leave stray
+    /// values behind.
+    pub fn set_debug_checks(&mut self, enabled: bool) {
+        self.debug_checks = enabled;
+    }
+
+    /// Drains the warnings raised while running, leaving it to the caller to decide how to
+    /// report them (the VM itself never prints).
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Drains the values passed to 'print' while running, oldest first.
+    pub fn take_output(&mut self) -> Vec<StackItem> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Registers a function implemented in Rust, callable as `name(...)` with exactly `arity`
+    /// arguments. Like the built-in natives, it can still be shadowed by a user `let`.
+    pub fn register_native(&mut self, name: &str, arity: usize, func: NativeFn) {
+        self.natives.register(name, arity, func);
+    }
+
+    /// Compiles `body` as a function of `params` and binds it to `name`, just as running
+    /// 'let name = (params) => body' would.
+    pub fn define_function(
+        &mut self,
+        name: &str,
+        params: &[&str],
+        body: &str,
+    ) -> Result<(), String> {
+        for ident in std::iter::once(&name).chain(params) {
+            let tokens: Result<Vec<_>, _> = crate::token_iter(ident).collect();
+            if tokens != Ok(vec![Token::Identifier(ident.to_string())]) {
+                return Err(format!("'{ident}' is not a valid identifier"));
+            }
+        }
+        // a body of several statements would otherwise leak all but the first out of the function
+        if let compiler::RecursiveExpression::Sequence(_) = crate::parse(body)? {
+            return Err(String::from("function body must be a single expression"));
+        }
+        let source = format!("let {name} = ({}) => {body}", params.join(", "));
+        let program = crate::compile(&source)?;
+        self.run(&program)
+    }
+
     pub fn run(&mut self, program: &[Instruction]) -> Result<(), String> {
+        let depth = self.scopes.depth();
+        self.run_height = self.stack.len();
+        let mut result = self.execute(program);
+        if result.is_ok() && self.debug_checks {
+            result = self.execute(&[Instruction::AssertStackEmpty]);
+        }
+        if result.is_err() {
+            // an error skips any pending 'Leave's, so unwind scopes the failed program entered
+            self.scopes.truncate(depth);
+            self.tail_call = None;
+        }
+        self.finish_call_stack(result)
+    }
+
+    /// Lists the builtins, constants and variables in scope whose names start with `prefix`, sorted
+    /// by name, for suggesting completions of a partially typed identifier.
+    pub fn completions(&self, prefix: &str) -> Vec<Completion> {
+        let builtins = compiler::keyword_builtins();
+        let functions = builtins.functions.into_iter();
+        let functions = functions.chain(self.natives.functions());
+        let functions = functions.map(|x| (x.name, CompletionKind::Builtin));
+        let constants = builtins.constants.into_iter();
+        let constants = constants.map(|x| (x, CompletionKind::Constant));
+        let variables = self.scopes.bindings().map(|(name, value)| match value {
+            Value::Routine(_) | Value::Native(_) => (name.clone(), CompletionKind::Function),
+            _ => (name.clone(), CompletionKind::Variable),
+        });
+        let mut completions: Vec<_> = variables
+            .chain(functions)
+            .chain(constants)
+            .filter(|(name, _)| name.starts_with(prefix))
+            .map(|(name, kind)| Completion { name, kind })
+            .collect();
+        // variables come first, so a 'let' hiding a native is what the stable sort keeps
+        completions.sort_by(|a, b| a.name.cmp(&b.name));
+        completions.dedup_by(|a, b| a.name == b.name);
+        completions
+    }
+
+    /// Runs a program produced by `pool_constants`, resolving its `PushConst`s against its pool.
+    pub fn run_pooled(&mut self, program: &PooledProgram) -> Result<(), String> {
+        let constants = std::mem::replace(&mut self.constants, program.constants.clone());
+        let result = self.run(&program.instructions);
+        self.constants = constants;
+        result
+    }
+
+    /// Runs `program` like `run`, also recording every instruction executed (including those
+    /// inside called functions and taken branches) along with the resulting top of the stack.
+    pub fn run_traced(&mut self, program: &[Instruction]) -> (Result<(), String>, Vec<TraceStep>) {
+        self.trace = Some(vec![]);
+        let result = self.run(program);
+        (result, self.trace.take().unwrap_or_default())
+    }
+
+    // clears the calls left on the stack by an error, prefixing it with their trace if enabled
+    fn finish_call_stack(&mut self, result: Result<(), String>) -> Result<(), String> {
+        self.call_stack.clear();
+        let trace = self.error_trace.take();
+        result.map_err(|err| match trace {
+            Some(trace) if !self.omit_stack_trace => format!("in {}: {err}", trace.join(" -> ")),
+            _ => err,
+        })
+    }
+
+    /// Looks up the function bound to `name` once, so that it can be called repeatedly with
+    /// `call_resolved` without searching the scopes each time. The handle keeps the function as it
+    /// was when resolved, even if `name` is later rebound.
+    pub fn resolve_function(&self, name: &str) -> Option<FunctionHandle> {
+        let Some((_, Value::Routine(routine))) = self.scopes.get(name) else {
+            return None;
+        };
+        let arity = routine
+            .iter()
+            .take_while(|x| matches!(x, Instruction::ShadowAssign(_)))
+            .count();
+        Some(FunctionHandle {
+            name: name.to_string(),
+            arity,
+            routine: routine.clone(),
+        })
+    }
+
+    /// Calls a function resolved by `resolve_function`, returning its result (if it has one).
+    pub fn call_resolved(
+        &mut self,
+        handle: &FunctionHandle,
+        args: &[f64],
+    ) -> Result<Option<f64>, String> {
+        if args.len() != handle.arity {
+            return Err(format!(
+                "{} expects {} argument(s), got {}",
+                handle.name,
+                handle.arity,
+                args.len()
+            ));
+        }
+        let height = self.stack.len();
+        self.stack.extend(args.iter().map(|&x| Value::from(x)));
+        let result = self.invoke_routine(&handle.name, &handle.routine);
+        if result.is_err() {
+            self.tail_call = None;
+        }
+        let value = match self.stack.len() > height {
+            true => self.stack.pop().map(|x| x.as_number()),
+            false => None,
+        };
+        self.stack.truncate(height);
+        self.finish_call_stack(result)?;
+        Ok(value)
+    }
+
+    /// Runs `program` with `bindings` as locals in a fresh scope that is discarded afterwards, so
+    /// repeated evaluations (example: plotting 'x^2' over x) don't leak into the global scope.
+    pub fn run_with_bindings(
+        &mut self,
+        program: &[Instruction],
+        bindings: &[(&str, f64)],
+    ) -> Result<(), String> {
+        let depth = self.scopes.depth();
+        self.scopes.push();
+        for (name, value) in bindings {
+            self.scopes
+                .put(name.to_string(), (*value).into())
+                .expect("failed to put local");
+        }
+        let result = self.run(program);
+        self.scopes.truncate(depth);
+        result
+    }
+
+    fn execute(&mut self, program: &[Instruction]) -> Result<(), String> {
         for instruction in program {
+            if self.try_int_binary_op(instruction)? {
+                self.record_step(instruction);
+                continue;
+            }
             match instruction {
-                Instruction::Add => self.binary_op(|lhs, rhs| lhs + rhs)?,
+                Instruction::Add => self.add()?,
                 Instruction::Sub => self.binary_op(|lhs, rhs| lhs - rhs)?,
                 Instruction::Sine => self.unary_op(|x| x.to_radians().sin())?,
                 Instruction::Cosine => self.unary_op(|x| x.to_radians().cos())?,
                 Instruction::Log => self.unary_op(|x| x.log10())?,
                 Instruction::Round => self.unary_op(|x| x.round())?,
                 Instruction::Floor => self.unary_op(|x| x.floor())?,
+                Instruction::Log2 => self.unary_op(|x| x.log2())?,
+                Instruction::Csc => self.reciprocal_trig("csc", 0.0, |x| x.to_radians().sin())?,
+                Instruction::Sec => self.reciprocal_trig("sec", 90.0, |x| x.to_radians().cos())?,
+                Instruction::Cot => self.reciprocal_trig("cot", 0.0, |x| x.to_radians().tan())?,
+                Instruction::Erfc => self.unary_op(erfc)?,
+                Instruction::Erf => self.unary_op(erf)?,
+                // leaves its operand in place, so it can wrap any expression without changing it
+                Instruction::Print => {
+                    let top = self.stack.last();
+                    let top = top.ok_or_else(|| String::from("missing operand"))?;
+                    self.output.push(top.to_stack_item());
+                }
+                Instruction::Clamp01 => self.unary_op(|x| x.clamp(0.0, 1.0))?,
+                Instruction::Gamma => {
+                    let strict = self.strict_math;
+                    self.try_unary_op(|x| match gamma(x)? {
+                        result if strict && result.is_infinite() => {
+                            Err(format!("gamma of '{x}' is too large"))
+                        }
+                        result => Ok(result),
+                    })?
+                }
+                Instruction::Sqrt => {
+                    let strict = self.strict_math;
+                    self.try_unary_op(|x| match x {
+                        x if strict && x < 0.0 => {
+                            Err(format!("cannot take square root of negative number '{x}'"))
+                        }
+                        x => Ok(x.sqrt()),
+                    })?
+                }
                 Instruction::Push(x) => self.push(*x),
-                Instruction::LoadLocal(ident) => self.load_local(&ident),
+                Instruction::PushConst(idx) => {
+                    let x = self.constants.get(*idx).copied();
+                    let x = x.ok_or_else(|| format!("missing constant {idx}"))?;
+                    self.push(x)
+                }
+                Instruction::PushInt(x) => self.stack.push(Value::Int(*x)),
+                Instruction::PushStr(x) => self.stack.push(Value::Str(x.clone())),
+                Instruction::MakeArray(len) => self.make_array(*len)?,
+                Instruction::MakeTuple(len) => self.make_tuple(*len)?,
+                Instruction::Destructure(idents) => self.destructure(idents)?,
+                Instruction::Range(inclusive) => self.make_range(*inclusive)?,
+                Instruction::LoadLocal(ident) => self.load_local(ident)?,
                 Instruction::Assign(ident) => self.assign(ident)?,
+                Instruction::AssignConst(ident) => self.assign_const(ident)?,
                 Instruction::ShadowAssign(ident) => self.shadow_assign(ident)?,
-                Instruction::CallRoutine => self.call_routine()?,
+                Instruction::CallRoutine(ident, arity) => self.call_routine(ident, *arity)?,
+                Instruction::CallValue(arity) => self.call_routine("<anonymous>", *arity)?,
+                Instruction::TailCallRoutine(_) => self.tail_call_routine(),
                 Instruction::PushRoutine(routine) => self.push(routine.to_vec()),
                 Instruction::SkipIfNot(block) => self.conditional(|x| x != 0.0, block)?,
                 Instruction::IfElse(if_block, else_block) => {
@@ -95,39 +742,151 @@ impl VM {
                         .ok_or_else(|| String::from("missing operand"))?
                         .as_number();
                     if operand != 0.0 {
-                        self.run(if_block)?;
+                        self.execute(if_block)?;
                     } else {
-                        self.run(else_block)?;
+                        self.execute(else_block)?;
                     }
                 }
                 Instruction::PushRandom => self.push(self.rng.rand()),
+                Instruction::PushRandomNormal => {
+                    let z = self.rand_normal();
+                    self.push(z)
+                }
+                Instruction::RandomNormal => {
+                    let z = self.rand_normal();
+                    self.binary_op(|mean, std| mean + std * z)?
+                }
                 Instruction::Mul => self.binary_op(|lhs, rhs| lhs * rhs)?,
-                Instruction::Div => self.binary_op(|lhs, rhs| lhs / rhs)?,
-                Instruction::Mod => self.binary_op(|lhs, rhs| lhs % rhs)?,
+                Instruction::Div => {
+                    let strict = self.strict_math;
+                    self.try_binary_op(|lhs, rhs| match rhs {
+                        rhs if strict && rhs == 0.0 => Err(String::from("division by zero")),
+                        rhs => Ok(lhs / rhs),
+                    })?
+                }
+                Instruction::Mod => {
+                    let strict = self.strict_math;
+                    self.try_binary_op(|lhs, rhs| match rhs {
+                        rhs if strict && rhs == 0.0 => Err(String::from("modulo by zero")),
+                        rhs => Ok(lhs % rhs),
+                    })?
+                }
                 Instruction::Pow => self.binary_op(|lhs, rhs| lhs.powf(rhs))?,
-                Instruction::CmpEQ => self.binary_op(|lhs, rhs| (lhs == rhs) as u8 as f64)?,
-                Instruction::CmpNEQ => self.binary_op(|lhs, rhs| (lhs != rhs) as u8 as f64)?,
-                Instruction::CmpLT => self.binary_op(|lhs, rhs| (lhs < rhs) as u8 as f64)?,
-                Instruction::CmpLTE => self.binary_op(|lhs, rhs| (lhs <= rhs) as u8 as f64)?,
-                Instruction::CmpGT => self.binary_op(|lhs, rhs| (lhs > rhs) as u8 as f64)?,
-                Instruction::CmpGTE => self.binary_op(|lhs, rhs| (lhs >= rhs) as u8 as f64)?,
-                Instruction::Enter => self.scopes.push(),
+                Instruction::CmpEQ => self.equality(true)?,
+                Instruction::CmpNEQ => self.equality(false)?,
+                Instruction::CmpLT => self.comparison(|lhs, rhs| lhs < rhs)?,
+                Instruction::CmpLTE => self.comparison(|lhs, rhs| lhs <= rhs)?,
+                Instruction::CmpGT => self.comparison(|lhs, rhs| lhs > rhs)?,
+                Instruction::CmpGTE => self.comparison(|lhs, rhs| lhs >= rhs)?,
+                Instruction::Enter => self.scopes.push_block(self.stack.len()),
+                Instruction::Discard => self.stack.truncate(self.scopes.stack_height()),
+                Instruction::AssertStackEmpty => {
+                    // the program's own result may remain, but nothing beneath it that this run
+                    // added
+                    let height = self.scopes.stack_height().max(self.run_height);
+                    let stray = self.stack.len().saturating_sub(height + 1);
+                    if stray > 0 {
+                        return Err(format!("{stray} stray value(s) left on the stack"));
+                    }
+                }
                 Instruction::Leave => self.scopes.pop(),
+                Instruction::Not => self.unary_op(|x| (x == 0.0) as u8 as f64)?,
+                Instruction::Factorial => {
+                    let strict = self.strict_math;
+                    self.try_unary_op(|x| match factorial(x) {
+                        result if strict && result.is_infinite() => {
+                            Err(format!("factorial of '{x}' is too large"))
+                        }
+                        result => Ok(result),
+                    })?
+                }
+                Instruction::Map => self.map()?,
+                Instruction::Simulate => self.simulate()?,
+                Instruction::Index => self.index()?,
+                Instruction::IndexSet(ident) => self.index_set(ident)?,
+                Instruction::Min => self.extremum("min", f64::min)?,
+                Instruction::Max => self.extremum("max", f64::max)?,
+                Instruction::Beta => self.try_binary_op(beta)?,
+                Instruction::Reduce => self.reduce()?,
+                Instruction::Lerp => self.ternary_op(|a, b, t| a + (b - a) * t)?,
+                Instruction::Dup => {
+                    let top = self.stack.last().cloned();
+                    let top = top.ok_or_else(|| String::from("missing operand"))?;
+                    self.stack.push(top);
+                }
+                Instruction::Pop => {
+                    self.stack
+                        .pop()
+                        .ok_or_else(|| String::from("missing operand"))?;
+                }
+                Instruction::Swap => {
+                    let len = self.stack.len();
+                    if len < 2 {
+                        return Err(String::from("missing operand"));
+                    }
+                    self.stack.swap(len - 1, len - 2);
+                }
+            }
+            if let (Precision::F32, Some(top)) = (self.precision, self.stack.last_mut()) {
+                top.round_to_f32();
+            }
+            self.record_step(instruction);
+            if self.tail_call.is_some() {
+                // unwind to the enclosing call_routine, which reuses its frame for the tail call
+                break;
             }
         }
         Ok(())
     }
 
-    pub fn pop_result(&mut self) -> Option<f64> {
-        match self.stack.pop() {
-            Some(result) => Some(result.as_number()),
-            _ => {
-                dbg!(self);
-                None
-            }
+    fn record_step(&mut self, instruction: &Instruction) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceStep {
+                instruction: instruction.clone(),
+                top: self.stack.last().map(Value::to_stack_item),
+            });
         }
     }
 
+    /// Pops the program's result, or `None` if it left no value (example: 'let x = 1').
+    pub fn pop_result(&mut self) -> Option<f64> {
+        self.stack.pop().map(|result| result.as_number())
+    }
+
+    /// Pops the program's result without flattening it to a number, so arrays, strings and
+    /// functions can be told apart.
+    pub fn pop_value(&mut self) -> Option<StackItem> {
+        self.stack.pop().map(|result| result.to_stack_item())
+    }
+
+    /// Looks up a function stored in scope and reconstructs its parameters and source, returning
+    /// `None` if `name` isn't bound to a function or its body can't be decompiled.
+    pub fn get_function(&self, name: &str) -> Option<FunctionInfo> {
+        let Some((_, Value::Routine(routine))) = self.scopes.get(name) else {
+            return None;
+        };
+        let (params, body) = decompiler::decompile_routine(routine).ok()?;
+        let body = pretty::pretty_print(body, PrettyFormat::Spaced, Default::default());
+        Some(FunctionInfo { params, body })
+    }
+
+    /// Snapshot of the operand stack from bottom to top, for inspecting the VM between runs.
+    pub fn stack_view(&self) -> Vec<StackItem> {
+        self.stack.iter().map(Value::to_stack_item).collect()
+    }
+
+    pub fn clear_stack(&mut self) {
+        self.stack.clear();
+    }
+
+    pub fn scope_depth(&self) -> usize {
+        self.scopes.depth()
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
     pub fn peek_routine(&mut self) -> Option<&[Instruction]> {
         match self.stack.last() {
             Some(Value::Routine(routine)) => Some(routine.as_slice()),
@@ -135,26 +894,216 @@ impl VM {
         }
     }
 
+    pub fn peek_array(&self) -> Option<Vec<f64>> {
+        match self.stack.last() {
+            Some(Value::Array(elements)) => Some(elements.iter().map(Value::as_number).collect()),
+            _ => None,
+        }
+    }
+
+    pub fn peek_str(&self) -> Option<&str> {
+        match self.stack.last() {
+            Some(Value::Str(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn make_array(&mut self, len: usize) -> Result<(), String> {
+        self.check_array_len(len)?;
+        let start = self
+            .stack
+            .len()
+            .checked_sub(len)
+            .ok_or_else(|| String::from("missing array elements"))?;
+        let elements = self.stack.split_off(start);
+        self.stack.push(Value::Array(elements));
+        Ok(())
+    }
+
+    fn make_tuple(&mut self, len: usize) -> Result<(), String> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(len)
+            .ok_or_else(|| String::from("missing tuple elements"))?;
+        let elements = self.stack.split_off(start);
+        self.stack.push(Value::Tuple(elements));
+        Ok(())
+    }
+
+    fn destructure(&mut self, identifiers: &[String]) -> Result<(), String> {
+        let value = self.stack.pop();
+        let value = value.ok_or_else(|| String::from("missing assignment value"))?;
+        let Value::Tuple(elements) = value else {
+            return Err(String::from("can only destructure a tuple"));
+        };
+        if elements.len() != identifiers.len() {
+            return Err(format!(
+                "cannot destructure a {}-tuple into {} variables",
+                elements.len(),
+                identifiers.len()
+            ));
+        }
+        // fail before assigning any element, so a const target leaves the others untouched too
+        if let Some(identifier) = identifiers.iter().find(|x| self.scopes.is_const(x)) {
+            return Err(format!("cannot reassign const '{identifier}'"));
+        }
+        for (identifier, element) in identifiers.iter().zip(elements) {
+            self.stack.push(element);
+            self.assign(identifier)?;
+        }
+        Ok(())
+    }
+
+    fn make_range(&mut self, inclusive: bool) -> Result<(), String> {
+        let end = self.stack.pop();
+        let end = end.ok_or_else(|| String::from("missing rhs"))?.as_number();
+        let start = self.stack.pop();
+        let start = start
+            .ok_or_else(|| String::from("missing lhs"))?
+            .as_number();
+        let span = end - start;
+        let len = if inclusive {
+            span.floor() + 1.0
+        } else {
+            span.ceil()
+        };
+        // saturates for huge (or infinite) spans, which then fail the check
+        let len = if len > 0.0 { len as usize } else { 0 };
+        self.check_array_len(len)
+            .map_err(|err| format!("range {start}..{end} is too large: {err}"))?;
+        let elements = (0..len).map(|i| Value::Number(start + i as f64));
+        self.stack.push(Value::Array(elements.collect()));
+        Ok(())
+    }
+
+    fn add(&mut self) -> Result<(), String> {
+        let concat = matches!(
+            self.stack.as_slice(),
+            [.., Value::Str(_), _] | [.., Value::Str(_)]
+        );
+        if !concat {
+            return self.binary_op(|lhs, rhs| lhs + rhs);
+        }
+        let rhs = self.stack.pop();
+        let rhs = rhs.ok_or_else(|| String::from("missing rhs"))?;
+        let lhs = self.stack.pop();
+        let lhs = lhs.ok_or_else(|| String::from("missing lhs"))?;
+        let result = lhs.to_concat_string()? + &rhs.to_concat_string()?;
+        self.stack.push(Value::Str(result));
+        Ok(())
+    }
+
     fn unary_op(&mut self, op: impl FnOnce(f64) -> f64) -> Result<(), String> {
+        self.try_unary_op(|x| Ok(op(x)))
+    }
+
+    fn try_unary_op(&mut self, op: impl FnOnce(f64) -> Result<f64, String>) -> Result<(), String> {
         let operand = self.stack.pop();
         let operand = operand
             .ok_or_else(|| String::from("missing operand"))?
             .as_number();
-        let result = op(operand);
+        let result = op(operand)?;
         self.stack.push(result.into());
         Ok(())
     }
 
-    fn binary_op(&mut self, op: impl FnOnce(f64, f64) -> f64) -> Result<(), String> {
+    fn ternary_op(&mut self, op: impl FnOnce(f64, f64, f64) -> f64) -> Result<(), String> {
+        let mut operands = [0.0; 3];
+        for operand in operands.iter_mut().rev() {
+            let value = self.stack.pop();
+            *operand = value
+                .ok_or_else(|| String::from("missing operand"))?
+                .as_number();
+        }
+        let [arg1, arg2, arg3] = operands;
+        self.stack.push(op(arg1, arg2, arg3).into());
+        Ok(())
+    }
+
+    // 'pole' is the first angle in degrees, repeating every 180, where 'f' is zero. those are
+    // matched exactly since the likes of 'cos(90)' come out a hair off zero in floating point
+    fn reciprocal_trig(
+        &mut self,
+        name: &str,
+        pole: f64,
+        f: impl FnOnce(f64) -> f64,
+    ) -> Result<(), String> {
+        let strict = self.strict_math;
+        self.try_unary_op(|x| match (x - pole) % 180.0 == 0.0 {
+            true if strict => Err(format!("{name} is undefined at {x}")),
+            true => Ok(f64::INFINITY),
+            false => Ok(1.0 / f(x)),
+        })
+    }
+
+    fn binary_op(&mut self, op: impl Fn(f64, f64) -> f64) -> Result<(), String> {
+        self.try_binary_op(|lhs, rhs| Ok(op(lhs, rhs)))
+    }
+
+    // applies the instruction when both operands are integers, leaving the stack untouched and
+    // returning false when either isn't or the result isn't an integer (example: '7 / 2'), so the
+    // caller can fall back to floating point. overflow is handled according to 'int_overflow'
+    fn try_int_binary_op(&mut self, instruction: &Instruction) -> Result<bool, String> {
+        let Some(op) = int_binary_op(instruction) else {
+            return Ok(false);
+        };
+        let &[.., Value::Int(lhs), Value::Int(rhs)] = self.stack.as_slice() else {
+            return Ok(false);
+        };
+        let result = match op(lhs, rhs) {
+            Some(result) => result,
+            None => match int_overflow_op(instruction, rhs) {
+                Some((wrapping, saturating)) => match self.int_overflow {
+                    IntOverflow::Wrap => wrapping(lhs, rhs),
+                    IntOverflow::Saturate => saturating(lhs, rhs),
+                    IntOverflow::Error => {
+                        return Err(format!(
+                            "integer overflow in {instruction:?} of {lhs} and {rhs}"
+                        ));
+                    }
+                },
+                None => return Ok(false),
+            },
+        };
+        self.stack.truncate(self.stack.len() - 2);
+        self.stack.push(Value::Int(result));
+        Ok(true)
+    }
+
+    fn try_binary_op(
+        &mut self,
+        op: impl Fn(f64, f64) -> Result<f64, String>,
+    ) -> Result<(), String> {
         let rhs = self.stack.pop();
-        let rhs = rhs.ok_or_else(|| String::from("missing rhs"))?.as_number();
+        let rhs = rhs.ok_or_else(|| String::from("missing rhs"))?;
         let lhs = self.stack.pop();
-        let lhs = lhs.ok_or_else(|| String::from("missing lhs"))?.as_number();
-        let result = op(lhs, rhs);
-        self.stack.push(result.into());
+        let lhs = lhs.ok_or_else(|| String::from("missing lhs"))?;
+        let result = elementwise(lhs, rhs, &op)?;
+        self.stack.push(result);
         Ok(())
     }
 
+    // arrays and tuples are equal when all their elements are, rather than compared elementwise
+    fn equality(&mut self, equal: bool) -> Result<(), String> {
+        let rhs = self.stack.pop();
+        let rhs = rhs.ok_or_else(|| String::from("missing rhs"))?;
+        let lhs = self.stack.pop();
+        let lhs = lhs.ok_or_else(|| String::from("missing lhs"))?;
+        let result = values_equal(&lhs, &rhs)? == equal;
+        self.push(result as u8 as f64);
+        Ok(())
+    }
+
+    fn comparison(&mut self, op: impl Fn(f64, f64) -> bool) -> Result<(), String> {
+        if let [.., lhs, rhs] = self.stack.as_slice() {
+            if lhs.is_function() || rhs.is_function() {
+                return Err(String::from("cannot compare functions"));
+            }
+        }
+        self.binary_op(|lhs, rhs| op(lhs, rhs) as u8 as f64)
+    }
+
     fn conditional(
         &mut self,
         op: impl FnOnce(f64) -> bool,
@@ -165,7 +1114,7 @@ impl VM {
             .ok_or_else(|| String::from("missing operand"))?
             .as_number();
         if op(operand) {
-            self.run(block)?;
+            self.execute(block)?;
         }
         Ok(())
     }
@@ -174,18 +1123,36 @@ impl VM {
         self.stack.push(x.into());
     }
 
-    fn load_local(&mut self, identifier: &str) {
+    fn load_local(&mut self, identifier: &str) -> Result<(), String> {
         let x = self.scopes.get(identifier).map(|(_, x)| x.clone());
+        // natives sit beneath every scope, so any user binding of the same name wins
+        let x = x.or_else(|| self.natives.get(identifier).map(Value::Native));
 
-        let x = x.unwrap_or_else(|| {
-            eprintln!("WARN: missing variable '{identifier}'");
-            0.0.into()
-        });
+        let x = match (x, self.missing_default) {
+            (Some(x), _) => x,
+            (None, MissingPolicy::Error) => {
+                return Err(format!("missing variable '{identifier}'"));
+            }
+            (None, policy) => {
+                self.warn(format!("missing variable '{identifier}'"));
+                match policy {
+                    MissingPolicy::Nan => f64::NAN.into(),
+                    _ => 0.0.into(),
+                }
+            }
+        };
 
-        self.stack.push(x.into());
+        self.stack.push(x);
+        Ok(())
     }
 
+    // captures are by reference: an assignment updates the nearest existing binding, searching out
+    // through every frame on the scope stack (including those of the callers that are running a
+    // closure), and only creates a new local in the current scope when there's none to update
     fn assign(&mut self, identifier: &str) -> Result<(), String> {
+        if self.scopes.is_const(identifier) {
+            return Err(format!("cannot reassign const '{identifier}'"));
+        }
         let value = self.stack.pop();
         let value = value.ok_or_else(|| String::from("missing assignment value"))?;
         if let Some((_, x)) = self.scopes.get_mut(identifier) {
@@ -194,10 +1161,11 @@ impl VM {
         }
 
         if identifier == "dbg" {
-            println!(
+            let message = format!(
                 "entered dbg point '{value}'. vm state = {self:#?}",
                 value = value.as_number()
             );
+            self.warn(message);
             return Ok(());
         }
 
@@ -207,6 +1175,17 @@ impl VM {
         Ok(())
     }
 
+    // unlike 'let', a const always declares in the current scope, so one inside a function can't
+    // update (or freeze) a caller's variable of the same name
+    fn assign_const(&mut self, identifier: &str) -> Result<(), String> {
+        if self.scopes.is_const_local(identifier) {
+            return Err(format!("cannot reassign const '{identifier}'"));
+        }
+        self.shadow_assign(identifier)?;
+        self.scopes.mark_const(identifier);
+        Ok(())
+    }
+
     fn shadow_assign(&mut self, identifier: &str) -> Result<(), String> {
         let value = self.stack.pop();
         let value = value.ok_or_else(|| String::from("missing assignment value"))?;
@@ -217,28 +1196,488 @@ impl VM {
         Ok(())
     }
 
-    fn call_routine(&mut self) -> Result<(), String> {
+    fn map(&mut self) -> Result<(), String> {
+        let routine = self.pop_callback("map")?;
+        let elements = self.pop_array("map")?;
+        let elements = elements
+            .into_iter()
+            .map(|x| self.call_with_args("map", &routine, vec![x]))
+            .collect::<Result<_, _>>()?;
+        self.stack.push(Value::Array(elements));
+        Ok(())
+    }
+
+    // calls a function without arguments 'n' times and pushes the mean of its results
+    fn simulate(&mut self) -> Result<(), String> {
+        let runs = self.stack.pop();
+        let runs = runs
+            .ok_or_else(|| String::from("missing operand"))?
+            .as_number();
+        let routine = self.pop_callback("simulate")?;
+        if runs < 1.0 || runs.fract() != 0.0 {
+            return Err(format!(
+                "simulate expects a positive whole number of runs, not {runs}"
+            ));
+        }
+        let max_runs = self.max_simulate_runs.unwrap_or(DEFAULT_MAX_SIMULATE_RUNS);
+        if runs > max_runs as f64 {
+            return Err(format!("{runs} runs exceeds the limit of {max_runs}"));
+        }
+        let runs = runs as usize;
+        let mut sum = 0.0;
+        for _ in 0..runs {
+            sum += match self.call_with_args("simulate", &routine, vec![])? {
+                Value::Number(x) => x,
+                Value::Int(x) => x as f64,
+                _ => {
+                    return Err(String::from(
+                        "simulate expects its function to return a number",
+                    ))
+                }
+            };
+        }
+        self.stack.push((sum / runs as f64).into());
+        Ok(())
+    }
+
+    fn reduce(&mut self) -> Result<(), String> {
+        let init = self.stack.pop();
+        let init = init.ok_or_else(|| String::from("missing operand"))?;
+        let routine = self.pop_callback("reduce")?;
+        let elements = self.pop_array("reduce")?;
+        let result = elements.into_iter().try_fold(init, |acc, x| {
+            self.call_with_args("reduce", &routine, vec![acc, x])
+        })?;
+        self.stack.push(result);
+        Ok(())
+    }
+
+    fn extremum(&mut self, name: &str, op: impl Fn(f64, f64) -> f64) -> Result<(), String> {
+        let value = self.stack.pop();
+        let result = match value.ok_or_else(|| String::from("missing operand"))? {
+            Value::Array(elements) => elements
+                .iter()
+                .map(Value::as_number)
+                .reduce(op)
+                .ok_or_else(|| format!("cannot take the {name} of an empty array"))?,
+            value => value.as_number(),
+        };
+        self.stack.push(result.into());
+        Ok(())
+    }
+
+    fn index(&mut self) -> Result<(), String> {
+        let index = self.stack.pop();
+        let index = index
+            .ok_or_else(|| String::from("missing index"))?
+            .as_number();
+        let target = self.stack.pop();
+        let Value::Array(mut elements) = target.ok_or_else(|| String::from("missing operand"))?
+        else {
+            return Err(String::from(
+                "cannot index into a value that is not an array",
+            ));
+        };
+        let idx = array_index(index, elements.len())?;
+        self.stack.push(elements.swap_remove(idx));
+        Ok(())
+    }
+
+    fn index_set(&mut self, identifier: &str) -> Result<(), String> {
+        if self.scopes.is_const(identifier) {
+            return Err(format!("cannot reassign const '{identifier}'"));
+        }
+        let value = self.stack.pop();
+        let value = value.ok_or_else(|| String::from("missing assignment value"))?;
+        let index = self.stack.pop();
+        let index = index
+            .ok_or_else(|| String::from("missing index"))?
+            .as_number();
+        let Some((_, target)) = self.scopes.get_mut(identifier) else {
+            return Err(format!("missing variable '{identifier}'"));
+        };
+        let Value::Array(elements) = target else {
+            return Err(format!(
+                "cannot index into '{identifier}' as it is not an array"
+            ));
+        };
+        let idx = array_index(index, elements.len())?;
+        elements[idx] = value;
+        Ok(())
+    }
+
+    fn pop_callback(&mut self, caller: &str) -> Result<Vec<Instruction>, String> {
+        match self.stack.pop() {
+            Some(Value::Routine(routine)) => Ok(routine),
+            Some(_) => Err(format!("{caller} expects a function")),
+            None => Err(String::from("missing operand")),
+        }
+    }
+
+    fn pop_array(&mut self, caller: &str) -> Result<Vec<Value>, String> {
         match self.stack.pop() {
-            Some(Value::Routine(routine)) => {
-                self.scopes.push();
-                let result = self.run(&routine);
-                self.scopes.pop();
-                result
+            Some(Value::Array(elements)) => Ok(elements),
+            Some(_) => Err(format!("{caller} expects an array")),
+            None => Err(String::from("missing operand")),
+        }
+    }
+
+    // calls a routine from within an instruction, expecting it to produce exactly one value
+    fn call_with_args(
+        &mut self,
+        identifier: &str,
+        routine: &[Instruction],
+        args: Vec<Value>,
+    ) -> Result<Value, String> {
+        let height = self.stack.len();
+        self.stack.extend(args);
+        self.invoke_routine(identifier, routine)?;
+        if self.stack.len() <= height {
+            return Err(format!(
+                "{identifier} expects its function to return a value"
+            ));
+        }
+        let result = self.stack.pop();
+        self.stack.truncate(height);
+        result.ok_or_else(|| String::from("missing operand"))
+    }
+
+    fn call_routine(&mut self, identifier: &str, arity: usize) -> Result<(), String> {
+        if let Some(&Value::Native(native)) = self.stack.last() {
+            self.stack.pop();
+            return self.call_native(identifier, native, arity);
+        }
+        match self.pop_routine() {
+            Some(routine) => self.invoke_routine(identifier, &routine),
+            None => Ok(()),
+        }
+    }
+
+    fn call_native(
+        &mut self,
+        identifier: &str,
+        native: Native,
+        arity: usize,
+    ) -> Result<(), String> {
+        if arity != native.arity {
+            return Err(format!(
+                "{identifier} expects {} argument(s), got {arity}",
+                native.arity
+            ));
+        }
+        let start = self
+            .stack
+            .len()
+            .checked_sub(arity)
+            .ok_or_else(|| String::from("missing operand"))?;
+        let args: Vec<_> = self.stack.drain(start..).map(|x| x.as_number()).collect();
+        let result = (native.func)(&args)?;
+        self.stack.push(result.into());
+        Ok(())
+    }
+
+    fn invoke_routine(&mut self, identifier: &str, routine: &[Instruction]) -> Result<(), String> {
+        let depth = self.scopes.depth();
+        self.call_stack.push(identifier.to_string());
+        let mut routine = std::borrow::Cow::Borrowed(routine);
+        let result = loop {
+            self.scopes.push();
+            let result = self.execute(&routine);
+            // tail calls can leave from inside nested blocks, so restore the depth directly
+            self.scopes.truncate(depth);
+            if let Err(err) = result {
+                if self.error_trace.is_none() {
+                    self.error_trace = Some(self.call_stack.clone());
+                }
+                break Err(err);
+            }
+            match self.tail_call.take() {
+                Some(next) => routine = std::borrow::Cow::Owned(next),
+                None => break Ok(()),
             }
+        };
+        self.call_stack.pop();
+        result
+    }
+
+    /// Self-recursive calls in tail position replace the current frame rather than nesting a new
+    /// one. The caller's locals are dropped before the call, so unlike a regular call the callee
+    /// can no longer see them.
+    fn tail_call_routine(&mut self) {
+        if let Some(routine) = self.pop_routine() {
+            self.tail_call = Some(routine);
+        }
+    }
+
+    fn pop_routine(&mut self) -> Option<Vec<Instruction>> {
+        match self.stack.pop() {
+            Some(Value::Routine(routine)) => Some(routine),
             Some(x) => {
-                eprintln!("WARN: current value is not callable '{x:?}'");
-                Ok(())
+                self.warn(format!("current value is not callable '{x:?}'"));
+                None
             }
             None => {
-                eprintln!("WARN: no current value to call");
-                Ok(())
+                self.warn(String::from("no current value to call"));
+                None
+            }
+        }
+    }
+
+    pub(crate) fn warn(&mut self, message: String) {
+        self.warnings.push(message);
+    }
+}
+
+// negative indices count back from the end (example: '-1' is the last element). fractional
+// indices are an error rather than being rounded, as they're more likely a mistake than intended
+fn array_index(index: f64, len: usize) -> Result<usize, String> {
+    if index.fract() != 0.0 {
+        return Err(format!("array index must be a whole number, got {index}"));
+    }
+    let idx = if index < 0.0 {
+        index + len as f64
+    } else {
+        index
+    };
+    if idx < 0.0 || idx >= len as f64 {
+        return Err(format!(
+            "array index {index} out of bounds for length {len}"
+        ));
+    }
+    Ok(idx as usize)
+}
+
+fn int_binary_op(instruction: &Instruction) -> Option<fn(i64, i64) -> Option<i64>> {
+    let op: fn(i64, i64) -> Option<i64> = match instruction {
+        Instruction::Add => i64::checked_add,
+        Instruction::Sub => i64::checked_sub,
+        Instruction::Mul => i64::checked_mul,
+        // only exact quotients stay integers (example: '7 / 2' is still 3.5)
+        Instruction::Div => |lhs, rhs| match lhs.checked_rem(rhs)? {
+            0 => lhs.checked_div(rhs),
+            _ => None,
+        },
+        Instruction::Mod => i64::checked_rem,
+        Instruction::Pow => |lhs, rhs| lhs.checked_pow(rhs.try_into().ok()?),
+        Instruction::CmpEQ => |lhs, rhs| Some((lhs == rhs) as i64),
+        Instruction::CmpNEQ => |lhs, rhs| Some((lhs != rhs) as i64),
+        Instruction::CmpLT => |lhs, rhs| Some((lhs < rhs) as i64),
+        Instruction::CmpLTE => |lhs, rhs| Some((lhs <= rhs) as i64),
+        Instruction::CmpGT => |lhs, rhs| Some((lhs > rhs) as i64),
+        Instruction::CmpGTE => |lhs, rhs| Some((lhs >= rhs) as i64),
+        _ => return None,
+    };
+    Some(op)
+}
+
+type IntOp = fn(i64, i64) -> i64;
+
+// the wrapping and saturating forms of an integer op that failed by overflowing. anything else
+// without an exact integer result (example: '2^(0 - 1)') is left to floating point instead
+fn int_overflow_op(instruction: &Instruction, rhs: i64) -> Option<(IntOp, IntOp)> {
+    let ops: (IntOp, IntOp) = match instruction {
+        Instruction::Add => (i64::wrapping_add, i64::saturating_add),
+        Instruction::Sub => (i64::wrapping_sub, i64::saturating_sub),
+        Instruction::Mul => (i64::wrapping_mul, i64::saturating_mul),
+        Instruction::Pow if rhs >= 0 => (
+            |lhs, rhs| lhs.wrapping_pow(rhs.try_into().unwrap_or(u32::MAX)),
+            |lhs, rhs| lhs.saturating_pow(rhs.try_into().unwrap_or(u32::MAX)),
+        ),
+        _ => return None,
+    };
+    Some(ops)
+}
+
+pub fn format_number(x: f64) -> String {
+    format!("{x}")
+}
+
+// compares structurally rather than coercing to numbers, so values of different types are
+// unequal (example: '1 == "1"')
+fn values_equal(lhs: &Value, rhs: &Value) -> Result<bool, String> {
+    match (lhs, rhs) {
+        _ if lhs.is_function() || rhs.is_function() => {
+            Err(String::from("cannot compare functions"))
+        }
+        (Value::Array(lhs), Value::Array(rhs)) | (Value::Tuple(lhs), Value::Tuple(rhs)) => {
+            if lhs.len() != rhs.len() {
+                return Ok(false);
+            }
+            for (lhs, rhs) in lhs.iter().zip(rhs) {
+                if !values_equal(lhs, rhs)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        (Value::Str(lhs), Value::Str(rhs)) => Ok(lhs == rhs),
+        (Value::Array(_) | Value::Tuple(_) | Value::Str(_), _)
+        | (_, Value::Array(_) | Value::Tuple(_) | Value::Str(_)) => Ok(false),
+        (lhs, rhs) => Ok(lhs.as_number() == rhs.as_number()),
+    }
+}
+
+// applies a numeric operator pairwise across arrays, broadcasting any scalar operand
+fn elementwise(
+    lhs: Value,
+    rhs: Value,
+    op: &impl Fn(f64, f64) -> Result<f64, String>,
+) -> Result<Value, String> {
+    let elements: Result<Vec<_>, _> = match (lhs, rhs) {
+        (Value::Array(lhs), Value::Array(rhs)) => {
+            if lhs.len() != rhs.len() {
+                return Err(format!(
+                    "array length mismatch: {} vs {}",
+                    lhs.len(),
+                    rhs.len()
+                ));
             }
+            lhs.into_iter()
+                .zip(rhs)
+                .map(|(lhs, rhs)| elementwise(lhs, rhs, op))
+                .collect()
         }
+        (Value::Array(lhs), rhs) => lhs
+            .into_iter()
+            .map(|lhs| elementwise(lhs, rhs.clone(), op))
+            .collect(),
+        (lhs, Value::Array(rhs)) => rhs
+            .into_iter()
+            .map(|rhs| elementwise(lhs.clone(), rhs, op))
+            .collect(),
+        (lhs, rhs) => return Ok(op(lhs.as_number(), rhs.as_number())?.into()),
+    };
+    elements.map(Value::Array)
+}
+
+// Lanczos approximation (g = 7, n = 9), reflected for x < 0.5
+fn gamma(x: f64) -> Result<f64, String> {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x <= 0.0 && x.fract() == 0.0 {
+        return Err(format!("gamma is undefined at {x}"));
+    }
+    if x < 0.5 {
+        let reflected = gamma(1.0 - x)?;
+        return Ok(std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * reflected));
+    }
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+    let series = (1..COEFFICIENTS.len())
+        .map(|i| COEFFICIENTS[i] / (x + i as f64))
+        .sum::<f64>()
+        + COEFFICIENTS[0];
+    // 't^(x+0.5)' alone overflows long before the result does (example: 'gamma(171)'), so it is
+    // applied in two halves either side of the shrinking 'e^-t'
+    let half_power = t.powf((x + 0.5) / 2.0);
+    Ok((2.0 * std::f64::consts::PI).sqrt() * half_power * (-t).exp() * half_power * series)
+}
+
+fn beta(a: f64, b: f64) -> Result<f64, String> {
+    let numerator = gamma(a)? * gamma(b)?;
+    match gamma(a + b) {
+        Ok(denominator) => Ok(numerator / denominator),
+        // gamma(a + b) is infinite at its poles, so the ratio vanishes
+        Err(_) => Ok(0.0),
+    }
+}
+
+// derived from the erfc fit below, so it shares its 1.2e-7 error bound
+fn erf(x: f64) -> f64 {
+    (1.0 - erfc(x.abs())).copysign(x)
+}
+
+// computed directly rather than as '1 - erf(x)', which cancels to 0 in the tail (example:
+// 'erfc(10)'). the chebyshev fit keeps a relative error below 1.2e-7 for every x
+fn erfc(x: f64) -> f64 {
+    const A: [f64; 10] = [
+        -1.265_512_23,
+        1.000_023_68,
+        0.374_091_96,
+        0.096_784_18,
+        -0.186_288_06,
+        0.278_868_07,
+        -1.135_203_98,
+        1.488_515_87,
+        -0.822_152_23,
+        0.170_872_77,
+    ];
+
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = A.iter().rev().fold(0.0, |acc, a| acc * t + a);
+    let y = t * (poly - z * z).exp();
+    if x < 0.0 {
+        2.0 - y
+    } else {
+        y
     }
 }
 
+fn factorial(x: f64) -> f64 {
+    if x < 0.0 || x.fract() != 0.0 {
+        return f64::NAN;
+    }
+    // anything past 170! overflows to inf, so there's no need to keep multiplying
+    (1..=x.min(171.0) as u64).map(|i| i as f64).product()
+}
+
+#[derive(Debug, Clone)]
+struct NativeRegistry(Vec<(String, Native)>);
+
+impl Default for NativeRegistry {
+    fn default() -> Self {
+        let mut natives = Self(vec![]);
+        natives.register("toRadians", 1, |args| Ok(args[0].to_radians()));
+        natives.register("toFahrenheit", 1, |args| Ok(args[0] * 9.0 / 5.0 + 32.0));
+        natives.register("toCelsius", 1, |args| Ok((args[0] - 32.0) * 5.0 / 9.0));
+        natives
+    }
+}
+
+// the natives every VM starts with, described like the builtin keyword functions
+pub(crate) fn default_natives() -> Vec<compiler::BuiltinFunction> {
+    NativeRegistry::default().functions()
+}
+
+impl NativeRegistry {
+    fn functions(&self) -> Vec<compiler::BuiltinFunction> {
+        let functions = self
+            .0
+            .iter()
+            .map(|(name, native)| compiler::BuiltinFunction {
+                name: name.clone(),
+                arities: vec![native.arity],
+            });
+        functions.collect()
+    }
+    fn get(&self, name: &str) -> Option<Native> {
+        self.0.iter().find(|(x, _)| x == name).map(|(_, x)| *x)
+    }
+    fn register(&mut self, name: &str, arity: usize, func: NativeFn) {
+        let native = Native { arity, func };
+        match self.0.iter_mut().find(|(x, _)| x == name) {
+            Some((_, x)) => *x = native,
+            None => self.0.push((name.to_string(), native)),
+        }
+    }
+}
+
+// a scope's locals, the operand stack height on entering it so that a block can drop whatever its
+// earlier statements leave behind, and the names of the locals declared 'const'
 #[derive(Debug, Default, Clone)]
-struct LocalScope(Vec<(String, Value)>);
+struct LocalScope(Vec<(String, Value)>, usize, Vec<String>);
 
 #[derive(Debug, Clone)]
 struct ScopeStack(Vec<LocalScope>);
@@ -268,12 +1707,53 @@ impl ScopeStack {
         }
         None
     }
+    // whether the binding `name` refers to was declared with 'const'
+    fn is_const(&self, name: &str) -> bool {
+        let layer = self
+            .position(name)
+            .and_then(|(layer_idx, _)| self.0.get(layer_idx));
+        layer.is_some_and(|locals| locals.2.iter().any(|x| x == name))
+    }
+    fn is_const_local(&self, name: &str) -> bool {
+        let locals = self.0.last();
+        locals.is_some_and(|locals| locals.2.iter().any(|x| x == name))
+    }
+    fn mark_const(&mut self, name: &str) {
+        let layer = self.position(name).map(|(layer_idx, _)| layer_idx);
+        if let Some(locals) = layer.and_then(|layer_idx| self.0.get_mut(layer_idx)) {
+            locals.2.push(name.to_string());
+        }
+    }
+    // every visible binding, skipping those hidden by a binding of the same name in an inner scope
+    fn bindings(&self) -> impl Iterator<Item = &(String, Value)> {
+        let locals = self.0.iter().rev().flat_map(|locals| &locals.0);
+        let mut seen = vec![];
+        locals.filter(move |(name, _)| match seen.contains(&name) {
+            true => false,
+            false => {
+                seen.push(name);
+                true
+            }
+        })
+    }
     pub fn push(&mut self) {
         self.0.push(Default::default())
     }
+    pub fn push_block(&mut self, stack_height: usize) {
+        self.0.push(LocalScope(vec![], stack_height, vec![]))
+    }
     pub fn pop(&mut self) {
         self.0.pop();
     }
+    pub fn stack_height(&self) -> usize {
+        self.0.last().map_or(0, |locals| locals.1)
+    }
+    pub fn depth(&self) -> usize {
+        self.0.len()
+    }
+    pub fn truncate(&mut self, depth: usize) {
+        self.0.truncate(depth);
+    }
     pub fn put(&mut self, name: String, value: Value) -> Result<bool, ()> {
         let locals = self.0.last_mut().ok_or(())?;
         if let Some((_, x)) = locals.0.iter_mut().find(|(x, _)| x == &name) {
@@ -286,18 +1766,45 @@ impl ScopeStack {
     }
 }
 
+#[cfg(not(feature = "sync"))]
 pub struct Rand(RefCell<tiny_rng::Rng>);
 
+#[cfg(feature = "sync")]
+pub struct Rand(Mutex<tiny_rng::Rng>);
+
 impl Rand {
+    #[cfg(not(feature = "sync"))]
     fn rand(&self) -> f64 {
         use tiny_rng::Rand;
         self.0.borrow_mut().rand_f64()
     }
+
+    #[cfg(feature = "sync")]
+    fn rand(&self) -> f64 {
+        use tiny_rng::Rand;
+        // a panic mid-draw cannot leave the generator state half-written, so a poisoned lock is
+        // safe to reuse
+        let mut rng = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        rng.rand_f64()
+    }
+}
+
+impl Rand {
+    fn from_seed(seed: u64) -> Self {
+        let rng = tiny_rng::Rand::from_seed(seed);
+        #[cfg(not(feature = "sync"))]
+        return Self(RefCell::new(rng));
+        #[cfg(feature = "sync")]
+        return Self(Mutex::new(rng));
+    }
 }
 
 impl Default for Rand {
     fn default() -> Self {
-        Self(RefCell::new(tiny_rng::Rand::from_seed(0)))
+        Self::from_seed(0)
     }
 }
 